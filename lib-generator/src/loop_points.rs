@@ -0,0 +1,52 @@
+use preset_model::decode::decode;
+use std::path::Path;
+
+// NOTE: Below this level the head and tail of a file count as silence
+const SILENCE: f32 = 0.003;
+// NOTE: Shorter sounds are one-shots, looping them only makes a stutter
+const MIN_LOOP: f32 = 2.0;
+// NOTE: The loop end is searched for in this share of the sounding part, counted from its end
+const END_SEARCH: f32 = 0.25;
+// NOTE: Seconds of waveform compared after each candidate point
+const MATCH_WINDOW: f32 = 0.05;
+
+// NOTE: Finds a loop whose end continues into its start without a seam. The start is the
+// first rising zero crossing of the sound, the end is the rising zero crossing near the tail
+// whose following waveform is closest to the one after the start
+pub fn find_loop_points(path: &Path) -> Option<(f32, f32)> {
+    let buffer = decode(path).ok()?;
+    let samples = buffer.mono();
+    let rate = buffer.sample_rate as f32;
+
+    let first = samples.iter().position(|s| s.abs() > SILENCE)?;
+    let last = samples.iter().rposition(|s| s.abs() > SILENCE)?;
+    let window = (MATCH_WINDOW * rate) as usize;
+    if (last - first) as f32 / rate < MIN_LOOP || last < window {
+        return None;
+    }
+
+    let start = rising_crossings(&samples, first, last - window).next()?;
+    let search_from = last - ((last - first) as f32 * END_SEARCH) as usize;
+    let reference = &samples[start..start + window];
+
+    let end = rising_crossings(&samples, search_from.max(start + 1), last - window).min_by(
+        |&a, &b| {
+            distance(reference, &samples[a..a + window])
+                .total_cmp(&distance(reference, &samples[b..b + window]))
+        },
+    )?;
+
+    if (end - start) as f32 / rate < MIN_LOOP {
+        return None;
+    }
+
+    Some((start as f32 / rate, end as f32 / rate))
+}
+
+fn rising_crossings(samples: &[f32], from: usize, to: usize) -> impl Iterator<Item = usize> + '_ {
+    (from.max(1)..to).filter(|&idx| samples[idx - 1] < 0.0 && samples[idx] >= 0.0)
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+mod loop_points;
+
+use loop_points::find_loop_points;
+use preset_model::{family_of, Category, Sample};
 use std::{
     collections::HashMap,
     fs,
@@ -20,7 +23,7 @@ fn main() {
                         if file_type.is_dir() {
                             let dir_name_os_str = file_path.file_name();
                             let dir_name = dir_name_os_str.to_str().unwrap();
-                            let current_category = get_category_by_str(dir_name);
+                            let current_category = dir_name.parse().unwrap_or(Category::Boom);
                             lib_hash.insert(current_category, vec![]);
 
                             match fs::read_dir(file_path.path()) {
@@ -39,6 +42,8 @@ fn main() {
                                             .unwrap()
                                             .as_secs_f32(),
                                             filename: filename.to_string(),
+                                            family: family_of(filename).to_string(),
+                                            loop_points: find_loop_points(&sample_path),
                                             filepath: format!(
                                                 "{SOUND_LIB_PATH}{dir_name}/{filename}.mp3"
                                             ),
@@ -74,32 +79,3 @@ fn main() {
         }
     }
 }
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq)]
-pub enum Category {
-    Boom,
-    Doors,
-    Construction,
-    Eerie,
-    People,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Sample {
-    pub id: String,
-    pub filename: String,
-    pub filepath: String,
-    pub category: Category,
-    pub duration: f32,
-}
-
-fn get_category_by_str(s: &str) -> Category {
-    match s {
-        "boom" => Category::Boom,
-        "doors" => Category::Doors,
-        "construction" => Category::Construction,
-        "eerie" => Category::Eerie,
-        "people" => Category::People,
-        _ => Category::Boom,
-    }
-}
@@ -0,0 +1,78 @@
+use std::{error::Error, fs, io::ErrorKind, path::Path};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+pub struct Buffer {
+    pub sample_rate: u32,
+    // NOTE: Always two channels, mono files are duplicated on decode
+    pub channels: [Vec<f32>; 2],
+}
+
+impl Buffer {
+    pub fn duration(&self) -> f64 {
+        self.channels[0].len() as f64 / f64::from(self.sample_rate)
+    }
+
+    pub fn mono(&self) -> Vec<f32> {
+        let [left, right] = &self.channels;
+        left.iter().zip(right).map(|(l, r)| (l + r) / 2.0).collect()
+    }
+}
+
+pub fn decode(path: &Path) -> Result<Buffer, Box<dyn Error>> {
+    let file = fs::File::open(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| format!("{}: no audio track", path.display()))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut channels = [Vec::new(), Vec::new()];
+    let mut sample_rate = 0;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(format!("{}: {err}", path.display()).into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // NOTE: A corrupt frame is skipped, the way browsers do
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(format!("{}: {err}", path.display()).into()),
+        };
+        let spec = *decoded.spec();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+
+        sample_rate = spec.rate;
+        for frame in samples.samples().chunks(spec.channels.count().max(1)) {
+            channels[0].push(frame[0]);
+            channels[1].push(frame.get(1).copied().unwrap_or(frame[0]));
+        }
+    }
+
+    Ok(Buffer {
+        sample_rate,
+        channels,
+    })
+}
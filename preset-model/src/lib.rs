@@ -0,0 +1,1416 @@
+use chrono::{DateTime, Utc};
+use core::fmt;
+use rand::distr::{Alphanumeric, SampleString};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[cfg(feature = "decode")]
+pub mod decode;
+
+pub const GRID_COLUMNS_MIN: u16 = 1;
+pub const GRID_COLUMNS_MAX: u16 = 12;
+// NOTE: Keeps a grid of empty cells with no gap from spinning the scheduler
+pub const MIN_STEP: f64 = 0.05;
+pub const MIN_RATE: f64 = 0.1;
+// NOTE: Excerpts cut into the middle of a sound, so their edges always get at least this fade
+pub const EXCERPT_FADE: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub enum Category {
+    Boom,
+    Doors,
+    People,
+    Construction,
+    Eerie,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Boom => write!(f, "boom"),
+            Category::Doors => write!(f, "doors"),
+            Category::People => write!(f, "people"),
+            Category::Construction => write!(f, "construction"),
+            Category::Eerie => write!(f, "eerie"),
+        }
+    }
+}
+
+impl Category {
+    pub fn get_emoji(&self) -> char {
+        match self {
+            Category::Boom => '🏀',
+            Category::Doors => '🚪',
+            Category::People => '🤦',
+            Category::Construction => '🔨',
+            Category::Eerie => '👻',
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = Category> {
+        [
+            Category::Boom,
+            Category::Doors,
+            Category::People,
+            Category::Construction,
+            Category::Eerie,
+        ]
+        .iter()
+        .copied()
+    }
+}
+
+impl FromStr for Category {
+    type Err = ();
+    fn from_str(v: &str) -> Result<Category, Self::Err> {
+        match v {
+            "boom" => Ok(Category::Boom),
+            "doors" => Ok(Category::Doors),
+            "people" => Ok(Category::People),
+            "construction" => Ok(Category::Construction),
+            "eerie" => Ok(Category::Eerie),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub id: String,
+    pub filename: String,
+    pub filepath: String,
+    pub category: Category,
+    pub duration: f32,
+    // NOTE: Variants share a family, `drill_1` to `drill_9` are all "drill"
+    #[serde(default)]
+    pub family: String,
+    // NOTE: Seconds where a seamless loop starts and ends, found by lib-generator
+    #[serde(default)]
+    pub loop_points: Option<(f32, f32)>,
+}
+
+impl Sample {
+    // NOTE: Samples saved before lib.json had families fall back to the filename
+    pub fn family_name(&self) -> &str {
+        if self.family.is_empty() {
+            family_of(&self.filename)
+        } else {
+            &self.family
+        }
+    }
+}
+
+pub fn family_of(filename: &str) -> &str {
+    filename
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end_matches('_')
+}
+
+impl Ord for Sample {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.filename.cmp(&other.filename)
+    }
+}
+
+impl PartialOrd for Sample {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Sample {
+    fn eq(&self, other: &Self) -> bool {
+        self.filename == other.filename
+    }
+}
+
+impl Eq for Sample {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Cell {
+    // NOTE: Flattened so grids saved as plain samples still deserialize
+    #[serde(flatten)]
+    pub sample: Sample,
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    #[serde(default = "default_repeat")]
+    pub repeat: u8,
+    #[serde(default = "default_rate")]
+    pub rate: f32,
+    #[serde(default)]
+    pub trim_start: f32,
+    #[serde(default)]
+    pub trim_end: f32,
+    #[serde(default)]
+    pub gap_override: Option<u64>,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub fade_in: Option<u64>,
+    #[serde(default)]
+    pub fade_out: Option<u64>,
+    #[serde(default)]
+    pub pool: CellPool,
+    // NOTE: Chance from 0 to 1 that the cell sounds, a failed roll leaves only its gap
+    #[serde(default = "default_probability")]
+    pub probability: f32,
+    // NOTE: -1 is hard left, 1 hard right
+    #[serde(default)]
+    pub pan: f32,
+    // NOTE: Milliseconds taken from a random point of the trimmed sample, none plays it whole
+    #[serde(default)]
+    pub excerpt: Option<u64>,
+    #[serde(default)]
+    pub looping: Option<Looping>,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_repeat() -> u8 {
+    1
+}
+
+fn default_rate() -> f32 {
+    1.0
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn default_probability() -> f32 {
+    1.0
+}
+
+impl Cell {
+    pub fn new(sample: Sample) -> Self {
+        Self {
+            sample,
+            gain: default_gain(),
+            repeat: default_repeat(),
+            rate: default_rate(),
+            trim_start: 0.0,
+            trim_end: 0.0,
+            gap_override: None,
+            weight: default_weight(),
+            fade_in: None,
+            fade_out: None,
+            pool: CellPool::default(),
+            probability: default_probability(),
+            pan: 0.0,
+            excerpt: None,
+            looping: None,
+        }
+    }
+
+    // NOTE: Samples the cell picks from each time it plays, its own sample when the pool
+    // has no other members. The library entry is preferred, it has the latest metadata
+    pub fn pool_members<'a>(
+        &'a self,
+        library: &'a HashMap<Category, Vec<Sample>>,
+    ) -> Vec<&'a Sample> {
+        let samples = library
+            .get(&self.sample.category)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let members = match self.pool {
+            CellPool::Sample => samples
+                .iter()
+                .filter(|sample| sample.filepath == self.sample.filepath)
+                .take(1)
+                .collect::<Vec<_>>(),
+            CellPool::Family => samples
+                .iter()
+                .filter(|sample| sample.family_name() == self.sample.family_name())
+                .collect(),
+            CellPool::Category => samples.iter().collect(),
+        };
+
+        if members.is_empty() {
+            vec![&self.sample]
+        } else {
+            members
+        }
+    }
+
+    // NOTE: Seconds the cell sounds for, including repeats and the rate change
+    pub fn play_duration(&self) -> f32 {
+        let trimmed = (self.sample.duration - self.trim_start - self.trim_end).max(0.0);
+        let trimmed = match (self.looping, self.excerpt) {
+            (Some(looping), _) => {
+                // NOTE: Same loop points as the voice gets, broken ones fall back to the trim
+                let (start, end) = self
+                    .sample
+                    .loop_points
+                    .filter(|&(start, end)| {
+                        f64::from(end) - f64::from(start) >= MIN_STEP && end <= self.sample.duration
+                    })
+                    .unwrap_or((self.trim_start, self.trim_start + trimmed));
+                let offset = self.trim_start.min(start);
+                looping.length(offset.into(), (start.into(), end.into()), self.rate.into()) as f32
+            }
+            (None, Some(excerpt)) => trimmed.min(excerpt as f32 / 1000.0),
+            (None, None) => trimmed,
+        };
+        trimmed / self.rate * f32::from(self.repeat.max(1))
+    }
+}
+
+// NOTE: Plays the sample on between its loop points instead of once
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Looping {
+    Seconds(u32),
+    Times(u32),
+}
+
+impl Looping {
+    pub fn amount(&self) -> u32 {
+        match *self {
+            Looping::Seconds(amount) | Looping::Times(amount) => amount,
+        }
+    }
+
+    // NOTE: Buffer seconds played from `offset` when the loop runs from `start` to `end`
+    pub fn length(&self, offset: f64, (start, end): (f64, f64), rate: f64) -> f64 {
+        match *self {
+            Looping::Seconds(secs) => f64::from(secs.max(1)) * rate,
+            Looping::Times(times) => {
+                (start - offset).max(0.0) + f64::from(times.max(1)) * (end - start)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CellPool {
+    #[default]
+    Sample,
+    Family,
+    Category,
+}
+
+impl CellPool {
+    pub fn iter() -> impl Iterator<Item = CellPool> {
+        [CellPool::Sample, CellPool::Family, CellPool::Category]
+            .iter()
+            .copied()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CellPool::Sample => "This sound",
+            CellPool::Family => "Any variant",
+            CellPool::Category => "Whole category",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Track {
+    pub id: String,
+    pub grid_data: Vec<Option<Cell>>,
+    pub gap_duration: u64,
+    #[serde(default)]
+    pub gap_max: u64,
+    #[serde(default)]
+    pub gap_distribution: GapDistribution,
+    #[serde(default)]
+    pub timing: TimingMode,
+    #[serde(default = "default_step_interval")]
+    pub step_interval: u64,
+    pub volume: f32,
+    // NOTE: Older saves hold a `random_playback` flag in place of the order
+    #[serde(
+        default,
+        alias = "random_playback",
+        deserialize_with = "deserialize_playback_order"
+    )]
+    pub playback_order: PlaybackOrder,
+    #[serde(default)]
+    pub loop_range: Option<(usize, usize)>,
+    #[serde(default)]
+    pub channel: Channel,
+}
+
+impl Track {
+    pub fn new(grid_data: Vec<Option<Cell>>) -> Self {
+        Self {
+            id: format!("track_{}", Alphanumeric.sample_string(&mut rand::rng(), 4)),
+            grid_data,
+            gap_duration: 1000,
+            gap_max: 1000,
+            gap_distribution: GapDistribution::default(),
+            timing: TimingMode::default(),
+            step_interval: default_step_interval(),
+            volume: 1.0,
+            playback_order: PlaybackOrder::default(),
+            loop_range: None,
+            channel: Channel::default(),
+        }
+    }
+
+    // NOTE: Inclusive cells playback cycles over, kept inside a grid that shrank since
+    pub fn loop_bounds(&self) -> (usize, usize) {
+        let last = self.grid_data.len().saturating_sub(1);
+        match self.loop_range {
+            Some((start, end)) => (start.min(last), end.min(last)),
+            None => (0, last),
+        }
+    }
+
+    // NOTE: Names of the settings that differ from `other`, the grid counts as one
+    pub fn changed_fields(&self, other: &Track) -> Vec<&'static str> {
+        [
+            ("grid_data", self.grid_data != other.grid_data),
+            ("gap_duration", self.gap_duration != other.gap_duration),
+            ("gap_max", self.gap_max != other.gap_max),
+            (
+                "gap_distribution",
+                self.gap_distribution != other.gap_distribution,
+            ),
+            ("timing", self.timing != other.timing),
+            ("step_interval", self.step_interval != other.step_interval),
+            ("volume", self.volume != other.volume),
+            (
+                "playback_order",
+                self.playback_order != other.playback_order,
+            ),
+            ("loop_range", self.loop_range != other.loop_range),
+            ("channel", self.channel != other.channel),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+
+    // NOTE: `gap_duration` is the lower bound, a max below it means a fixed gap
+    pub fn gap_range(&self) -> (u64, u64) {
+        (self.gap_duration, self.gap_max.max(self.gap_duration))
+    }
+
+    // NOTE: Seconds a cell holds sequential playback on average, the same sum the engine
+    // makes. Step timing gives every cell one step, gap timing the sound plus its gap less
+    // the crossfade, and an empty cell just the gap
+    pub fn cell_span(&self, cell: Option<&Cell>, envelope: &Envelope) -> f32 {
+        let span = match (self.timing, cell) {
+            (TimingMode::Step, _) => self.step_interval as f64 / 1000.0,
+            (TimingMode::Gap, None) => self.mean_gap(),
+            (TimingMode::Gap, Some(cell)) => {
+                let gap = match cell.gap_override {
+                    Some(gap_override) => gap_override as f64 / 1000.0,
+                    None => self.mean_gap(),
+                };
+                f64::from(cell.play_duration()) + gap - envelope.crossfade as f64 / 1000.0
+            }
+        };
+        span.max(MIN_STEP) as f32
+    }
+
+    pub fn cycle_length(&self, envelope: &Envelope) -> f32 {
+        self.grid_data
+            .iter()
+            .map(|cell| self.cell_span(cell.as_ref(), envelope))
+            .sum()
+    }
+
+    fn mean_gap(&self) -> f64 {
+        let (min, max) = self.gap_range();
+        self.gap_distribution
+            .mean(min as f64 / 1000.0, max as f64 / 1000.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PlaybackOrder {
+    #[default]
+    Sequential,
+    Shuffle,
+    Weighted,
+    AvoidCategory,
+    Markov,
+}
+
+impl PlaybackOrder {
+    pub fn cycle(&self) -> PlaybackOrder {
+        match self {
+            PlaybackOrder::Sequential => PlaybackOrder::Shuffle,
+            PlaybackOrder::Shuffle => PlaybackOrder::Weighted,
+            PlaybackOrder::Weighted => PlaybackOrder::AvoidCategory,
+            PlaybackOrder::AvoidCategory => PlaybackOrder::Markov,
+            PlaybackOrder::Markov => PlaybackOrder::Sequential,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlaybackOrder::Sequential => "In order",
+            PlaybackOrder::Shuffle => "Shuffle",
+            PlaybackOrder::Weighted => "Weighted",
+            PlaybackOrder::AvoidCategory => "Mix categories",
+            PlaybackOrder::Markov => "Chain",
+        }
+    }
+}
+
+impl From<bool> for PlaybackOrder {
+    fn from(random_playback: bool) -> Self {
+        if random_playback {
+            PlaybackOrder::Shuffle
+        } else {
+            PlaybackOrder::Sequential
+        }
+    }
+}
+
+fn deserialize_playback_order<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PlaybackOrder, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Legacy(bool),
+        Order(PlaybackOrder),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::Legacy(random_playback) => PlaybackOrder::from(random_playback),
+        Repr::Order(order) => order,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TimingMode {
+    #[default]
+    Gap,
+    Step,
+}
+
+impl fmt::Display for TimingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimingMode::Gap => write!(f, "gap"),
+            TimingMode::Step => write!(f, "step"),
+        }
+    }
+}
+
+impl FromStr for TimingMode {
+    type Err = ();
+    fn from_str(v: &str) -> Result<TimingMode, Self::Err> {
+        match v {
+            "gap" => Ok(TimingMode::Gap),
+            "step" => Ok(TimingMode::Step),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TimingMode {
+    pub fn iter() -> impl Iterator<Item = TimingMode> {
+        [TimingMode::Gap, TimingMode::Step].iter().copied()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimingMode::Gap => "Gap after sound",
+            TimingMode::Step => "Fixed step",
+        }
+    }
+}
+
+// NOTE: 120 BPM
+fn default_step_interval() -> u64 {
+    500
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GapDistribution {
+    #[default]
+    Uniform,
+    Normal,
+    Exponential,
+}
+
+impl fmt::Display for GapDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GapDistribution::Uniform => write!(f, "uniform"),
+            GapDistribution::Normal => write!(f, "normal"),
+            GapDistribution::Exponential => write!(f, "exponential"),
+        }
+    }
+}
+
+impl FromStr for GapDistribution {
+    type Err = ();
+    fn from_str(v: &str) -> Result<GapDistribution, Self::Err> {
+        match v {
+            "uniform" => Ok(GapDistribution::Uniform),
+            "normal" => Ok(GapDistribution::Normal),
+            "exponential" => Ok(GapDistribution::Exponential),
+            _ => Err(()),
+        }
+    }
+}
+
+impl GapDistribution {
+    pub fn iter() -> impl Iterator<Item = GapDistribution> {
+        [
+            GapDistribution::Uniform,
+            GapDistribution::Normal,
+            GapDistribution::Exponential,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn mean(&self, min: f64, max: f64) -> f64 {
+        let range = max - min;
+        if range <= 0.0 {
+            return min;
+        }
+
+        match self {
+            GapDistribution::Uniform | GapDistribution::Normal => min + range / 2.0,
+            // NOTE: Mean of the exponential in `sample`, cut off at the top of the range
+            GapDistribution::Exponential => {
+                let lambda = 3.0 / range;
+                let tail = (-lambda * range).exp();
+                min + 1.0 / lambda - range * tail / (1.0 - tail)
+            }
+        }
+    }
+
+    // NOTE: Every distribution stays inside [min, max], so the range is a hard limit
+    pub fn sample(&self, min: f64, max: f64, rng: &mut impl Rng) -> f64 {
+        let range = max - min;
+        if range <= 0.0 {
+            return min;
+        }
+
+        match self {
+            GapDistribution::Uniform => rng.random_range(min..=max),
+            GapDistribution::Normal => {
+                // NOTE: Box-Muller around the middle of the range, 3 sigma reach the bounds
+                let u1 = 1.0 - rng.random::<f64>();
+                let u2 = rng.random::<f64>();
+                let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+                (min + range / 2.0 + z * range / 6.0).clamp(min, max)
+            }
+            GapDistribution::Exponential => {
+                // NOTE: Inverse CDF of an exponential truncated to the range, mostly short
+                // gaps with an occasional long pause
+                let lambda = 3.0 / range;
+                let u = rng.random::<f64>();
+                min - (1.0 - u * (1.0 - (-lambda * range).exp())).ln() / lambda
+            }
+        }
+    }
+}
+
+// NOTE: All times are in ms, cells may override the fades with their own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Envelope {
+    pub fade_in: u64,
+    pub fade_out: u64,
+    pub crossfade: u64,
+    pub release: u64,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            fade_in: 0,
+            fade_out: 0,
+            crossfade: 0,
+            release: 1500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Channel {
+    #[default]
+    Both,
+    Left,
+    Right,
+}
+
+impl Channel {
+    pub fn iter() -> impl Iterator<Item = Channel> {
+        [Channel::Left, Channel::Both, Channel::Right]
+            .iter()
+            .copied()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Channel::Both => "L+R",
+            Channel::Left => "L",
+            Channel::Right => "R",
+        }
+    }
+
+    // NOTE: Routing to one side overrides the pan of the cell
+    pub fn pan(&self, pan: f32) -> f32 {
+        match self {
+            Channel::Both => pan.clamp(-1.0, 1.0),
+            Channel::Left => -1.0,
+            Channel::Right => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CategoryStrip {
+    pub channel: Channel,
+    pub volume: f32,
+}
+
+impl Default for CategoryStrip {
+    fn default() -> Self {
+        Self {
+            channel: Channel::default(),
+            volume: 1.0,
+        }
+    }
+}
+
+// NOTE: Per category settings of a preset, a category missing from the map uses the defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Mixer(HashMap<Category, CategoryStrip>);
+
+impl Mixer {
+    pub fn strip(&self, category: Category) -> CategoryStrip {
+        self.0.get(&category).copied().unwrap_or_default()
+    }
+
+    pub fn update(&mut self, category: Category, f: impl FnOnce(&mut CategoryStrip)) {
+        f(self.0.entry(category).or_default());
+    }
+
+    // NOTE: The track channel wins over the category channel, which wins over the cell pan
+    pub fn pan(&self, cell: &Cell, track_channel: Channel) -> f32 {
+        match track_channel {
+            Channel::Both => self.strip(cell.sample.category).channel.pan(cell.pan),
+            channel => channel.pan(cell.pan),
+        }
+    }
+}
+
+// NOTE: How likely one category is followed by another in the Markov order, a missing
+// weight counts as 1 so an untouched matrix plays categories evenly
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Transitions(HashMap<Category, HashMap<Category, f32>>);
+
+impl Transitions {
+    pub fn weight(&self, from: Category, to: Category) -> f32 {
+        self.0
+            .get(&from)
+            .and_then(|row| row.get(&to))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    // NOTE: Kept in the same 0 to 10 range as the cell weight, `inf` and `NaN` parse as
+    // numbers but would break the weighted pick
+    pub fn set_weight(&mut self, from: Category, to: Category, weight: f32) {
+        if !weight.is_finite() {
+            return;
+        }
+        self.0
+            .entry(from)
+            .or_default()
+            .insert(to, weight.clamp(0.0, 10.0));
+    }
+}
+
+// NOTE: Random spreads drawn each time a cell fires, pitch in semitones, volume in dB and
+// timing in ms
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Humanize {
+    pub pitch: f32,
+    pub volume: f32,
+    pub timing: u64,
+}
+
+impl Humanize {
+    // NOTE: Rate and gain factors plus the start delay in seconds for one trigger
+    pub fn draw(&self, rng: &mut impl Rng) -> (f32, f32, f64) {
+        let rate = if self.pitch > 0.0 {
+            2f32.powf(rng.random_range(-self.pitch..=self.pitch) / 12.0)
+        } else {
+            1.0
+        };
+        let gain = if self.volume > 0.0 {
+            10f32.powf(rng.random_range(-self.volume..=self.volume) / 20.0)
+        } else {
+            1.0
+        };
+        let delay = if self.timing > 0 {
+            rng.random_range(0..=self.timing) as f64 / 1000.0
+        } else {
+            0.0
+        };
+        (rate, gain, delay)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EqPreset {
+    #[default]
+    Flat,
+    BassBoost,
+    Rumble,
+    Transducer,
+    Custom,
+}
+
+impl EqPreset {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EqPreset::Flat => "Flat",
+            EqPreset::BassBoost => "Bass boost",
+            EqPreset::Rumble => "Deep rumble",
+            EqPreset::Transducer => "Vibro-speaker",
+            EqPreset::Custom => "Custom",
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = EqPreset> {
+        [
+            EqPreset::Flat,
+            EqPreset::BassBoost,
+            EqPreset::Rumble,
+            EqPreset::Transducer,
+        ]
+        .iter()
+        .copied()
+    }
+}
+
+// NOTE: Frequencies are in Hz and gains in dB
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Equalizer {
+    pub preset: EqPreset,
+    pub low_shelf_freq: f32,
+    pub low_shelf_gain: f32,
+    pub mid_freq: f32,
+    pub mid_gain: f32,
+    pub mid_q: f32,
+    pub high_cut_freq: f32,
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        Equalizer::from_preset(EqPreset::Flat)
+    }
+}
+
+impl Equalizer {
+    pub fn from_preset(preset: EqPreset) -> Self {
+        let (low_shelf_freq, low_shelf_gain, mid_freq, mid_gain, high_cut_freq) = match preset {
+            EqPreset::Flat | EqPreset::Custom => (100.0, 0.0, 250.0, 0.0, 20000.0),
+            EqPreset::BassBoost => (120.0, 6.0, 400.0, -2.0, 8000.0),
+            EqPreset::Rumble => (80.0, 9.0, 300.0, -4.0, 2000.0),
+            // NOTE: Structure-borne transducers barely pass anything above a few kHz
+            EqPreset::Transducer => (100.0, 8.0, 250.0, -3.0, 4000.0),
+        };
+
+        Self {
+            preset,
+            low_shelf_freq,
+            low_shelf_gain,
+            mid_freq,
+            mid_gain,
+            mid_q: 1.0,
+            high_cut_freq,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "PresetRepr")]
+pub struct Preset {
+    pub id: String,
+    pub name: String,
+    pub volume: f32,
+    pub tracks: Vec<Track>,
+    pub envelope: Envelope,
+    pub equalizer: Equalizer,
+    pub humanize: Humanize,
+    pub transitions: Transitions,
+    pub mixer: Mixer,
+    // NOTE: None in presets saved before the geometry was stored, the app then picks the
+    // column count that suits the screen
+    pub grid_columns: Option<u16>,
+    pub created: DateTime<Utc>,
+}
+
+// NOTE: Presets saved before tracks existed keep a single sequence at the top level
+#[derive(Deserialize)]
+struct PresetRepr {
+    id: String,
+    name: String,
+    volume: f32,
+    #[serde(default)]
+    tracks: Vec<Track>,
+    #[serde(default)]
+    envelope: Envelope,
+    #[serde(default)]
+    equalizer: Equalizer,
+    #[serde(default)]
+    humanize: Humanize,
+    #[serde(default)]
+    transitions: Transitions,
+    #[serde(default)]
+    mixer: Mixer,
+    #[serde(default)]
+    grid_columns: Option<u16>,
+    #[serde(default)]
+    gap_duration: u64,
+    #[serde(default)]
+    random_playback: bool,
+    #[serde(default)]
+    grid_data: Vec<Option<Cell>>,
+    created: DateTime<Utc>,
+}
+
+// NOTE: A corrupt column count would break every row calculation, so it is kept in bounds
+pub fn clamp_grid_columns(columns: u16) -> u16 {
+    columns.clamp(GRID_COLUMNS_MIN, GRID_COLUMNS_MAX)
+}
+
+impl From<PresetRepr> for Preset {
+    fn from(repr: PresetRepr) -> Self {
+        let tracks = if repr.tracks.is_empty() {
+            vec![Track {
+                gap_duration: repr.gap_duration,
+                gap_max: repr.gap_duration,
+                playback_order: PlaybackOrder::from(repr.random_playback),
+                ..Track::new(repr.grid_data)
+            }]
+        } else {
+            repr.tracks
+        };
+
+        Self {
+            id: repr.id,
+            name: repr.name,
+            volume: repr.volume,
+            tracks,
+            envelope: repr.envelope,
+            equalizer: repr.equalizer,
+            humanize: repr.humanize,
+            transitions: repr.transitions,
+            mixer: repr.mixer,
+            grid_columns: repr.grid_columns.map(clamp_grid_columns),
+            created: repr.created,
+        }
+    }
+}
+
+impl Ord for Preset {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.created.cmp(&other.created)
+    }
+}
+
+impl PartialOrd for Preset {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Preset {
+    fn eq(&self, other: &Self) -> bool {
+        self.created == other.created
+    }
+}
+
+impl Eq for Preset {}
+
+// NOTE: Cell played after `current`, the app sequencer and the preset renderer both walk
+// a grid with it. `bag` holds the cells left in a shuffle round
+pub fn next_cell(
+    grid: &[Option<Cell>],
+    (start, end): (usize, usize),
+    order: PlaybackOrder,
+    transitions: &Transitions,
+    current: usize,
+    bag: &mut Vec<usize>,
+    rng: &mut impl Rng,
+) -> usize {
+    // NOTE: A cell outside the loop leads back into it
+    if current < start || current > end {
+        return start;
+    }
+    let len = end - start + 1;
+    if len < 2 {
+        return start;
+    }
+    let other = |rng: &mut _| start + pick_other(current - start, len, rng);
+
+    match order {
+        PlaybackOrder::Sequential => start + (current - start + 1) % len,
+        PlaybackOrder::Shuffle => {
+            if bag.is_empty() {
+                *bag = (start..=end).collect();
+            }
+            // NOTE: A fresh round may not open with the cell that closed the last one
+            let candidates = bag
+                .iter()
+                .enumerate()
+                .filter(|&(_, &idx)| idx != current)
+                .map(|(pos, _)| pos)
+                .collect::<Vec<_>>();
+            match candidates.len() {
+                0 => {
+                    bag.clear();
+                    other(rng)
+                }
+                n => bag.swap_remove(candidates[rng.random_range(0..n)]),
+            }
+        }
+        PlaybackOrder::Weighted => {
+            let weights = (start..=end)
+                .map(|idx| match &grid[idx] {
+                    _ if idx == current => 0.0,
+                    Some(cell) => f64::from(cell.weight.max(0.0)),
+                    None => 1.0,
+                })
+                .collect::<Vec<_>>();
+            pick_weighted(&weights, rng).map_or_else(|| other(rng), |pos| start + pos)
+        }
+        PlaybackOrder::AvoidCategory => {
+            let category = grid[current].as_ref().map(|cell| cell.sample.category);
+            let candidates = (start..=end)
+                .filter(|&idx| {
+                    idx != current
+                        && (category.is_none()
+                            || grid[idx].as_ref().map(|cell| cell.sample.category) != category)
+                })
+                .collect::<Vec<_>>();
+            if candidates.is_empty() {
+                other(rng)
+            } else {
+                candidates[rng.random_range(0..candidates.len())]
+            }
+        }
+        PlaybackOrder::Markov => {
+            let Some(from) = grid[current].as_ref().map(|cell| cell.sample.category) else {
+                return other(rng);
+            };
+            let categories = (start..=end)
+                .filter(|&idx| idx != current)
+                .filter_map(|idx| grid[idx].as_ref().map(|cell| cell.sample.category))
+                .collect::<Vec<_>>();
+            // NOTE: The matrix weighs categories, so a category split over many cells
+            // is not picked more often
+            let weights = (start..=end)
+                .map(|idx| match &grid[idx] {
+                    Some(cell) if idx != current => {
+                        let to = cell.sample.category;
+                        let count = categories.iter().filter(|&&c| c == to).count();
+                        f64::from(transitions.weight(from, to)) / count as f64
+                    }
+                    _ => 0.0,
+                })
+                .collect::<Vec<_>>();
+            pick_weighted(&weights, rng).map_or_else(|| other(rng), |pos| start + pos)
+        }
+    }
+}
+
+// NOTE: Index picked with a chance proportional to its weight, None when all weights are zero
+// or a weight loaded from an old save is not finite
+fn pick_weighted(weights: &[f64], rng: &mut impl Rng) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if !total.is_finite() || total <= 0.0 {
+        return None;
+    }
+
+    let mut target = rng.random_range(0.0..total);
+    for (idx, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return Some(idx);
+        }
+        target -= weight;
+    }
+    None
+}
+
+// NOTE: Uniform pick that never repeats the current cell when there is a choice
+fn pick_other(current: usize, len: usize, rng: &mut impl Rng) -> usize {
+    if len < 2 {
+        return 0;
+    }
+
+    let next = rng.random_range(0..len - 1);
+    if next >= current {
+        next + 1
+    } else {
+        next
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VoiceParams {
+    pub offset: f64,
+    pub length: f64,
+    pub rate: f64,
+    pub gain: f32,
+    pub pan: f32,
+    pub fade_in: f64,
+    pub fade_out: f64,
+    // NOTE: Buffer seconds the source wraps between, it plays on for the whole length
+    pub looping: Option<(f64, f64)>,
+}
+
+impl VoiceParams {
+    pub fn full(duration: f64) -> Self {
+        Self {
+            offset: 0.0,
+            length: duration,
+            rate: 1.0,
+            gain: 1.0,
+            pan: 0.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            looping: None,
+        }
+    }
+
+    pub fn for_cell(cell: &Cell, duration: f64, envelope: &Envelope) -> Self {
+        let offset = f64::from(cell.trim_start).clamp(0.0, duration);
+        let length = (duration - offset - f64::from(cell.trim_end)).max(MIN_STEP);
+        // NOTE: Overlapping cells need fades at least as long as the overlap to blend
+        let crossfade = envelope.crossfade as f64 / 1000.0;
+        let fade_in = cell.fade_in.unwrap_or(envelope.fade_in) as f64 / 1000.0;
+        let fade_out = cell.fade_out.unwrap_or(envelope.fade_out) as f64 / 1000.0;
+
+        Self {
+            offset,
+            length,
+            rate: f64::from(cell.rate).max(MIN_RATE),
+            gain: cell.gain,
+            pan: cell.pan,
+            fade_in: fade_in.max(crossfade),
+            fade_out: fade_out.max(crossfade),
+            looping: None,
+        }
+    }
+
+    pub fn excerpt(self, length: f64, rng: &mut impl Rng) -> Self {
+        let length = length.max(MIN_STEP);
+        if length >= self.length {
+            return self;
+        }
+
+        Self {
+            offset: self.offset + rng.random_range(0.0..=self.length - length),
+            length,
+            fade_in: self.fade_in.max(EXCERPT_FADE),
+            fade_out: self.fade_out.max(EXCERPT_FADE),
+            ..self
+        }
+    }
+
+    // NOTE: Without loop points from the library the trimmed sample loops whole
+    pub fn looped(self, looping: Looping, points: Option<(f32, f32)>, duration: f64) -> Self {
+        let (start, end) = points
+            .map(|(start, end)| (f64::from(start), f64::from(end)))
+            .filter(|&(start, end)| end - start >= MIN_STEP && end <= duration)
+            .unwrap_or((self.offset, self.offset + self.length));
+        let offset = self.offset.min(start);
+
+        Self {
+            offset,
+            length: looping.length(offset, (start, end), self.rate),
+            looping: Some((start, end)),
+            ..self
+        }
+    }
+
+    // NOTE: Seconds on the audio clock, the rate stretches or squeezes the buffer time
+    pub fn span(&self) -> f64 {
+        self.length / self.rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn cell(category: Category, filename: &str) -> Option<Cell> {
+        Some(Cell::new(Sample {
+            id: format!("{category}_{filename}"),
+            filename: filename.to_string(),
+            filepath: format!("/public/sounds/{category}/{filename}.mp3"),
+            category,
+            duration: 4.0,
+            family: family_of(filename).to_string(),
+            loop_points: None,
+        }))
+    }
+
+    fn grid() -> Vec<Option<Cell>> {
+        vec![
+            cell(Category::Boom, "boom_1"),
+            cell(Category::Boom, "boom_2"),
+            None,
+            cell(Category::Doors, "door_1"),
+            cell(Category::Eerie, "wind_1"),
+            cell(Category::Eerie, "wind_2"),
+        ]
+    }
+
+    fn walk(
+        order: PlaybackOrder,
+        grid: &[Option<Cell>],
+        bounds: (usize, usize),
+        steps: usize,
+    ) -> Vec<usize> {
+        let transitions = Transitions::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut bag = Vec::new();
+        let mut current = bounds.0;
+        (0..steps)
+            .map(|_| {
+                current = next_cell(
+                    grid,
+                    bounds,
+                    order,
+                    &transitions,
+                    current,
+                    &mut bag,
+                    &mut rng,
+                );
+                current
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sequential_wraps_inside_the_loop() {
+        assert_eq!(
+            walk(PlaybackOrder::Sequential, &grid(), (1, 3), 6),
+            vec![2, 3, 1, 2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn every_order_enters_the_loop_at_its_start() {
+        let grid = grid();
+        let mut rng = StdRng::seed_from_u64(7);
+        for order in [
+            PlaybackOrder::Sequential,
+            PlaybackOrder::Shuffle,
+            PlaybackOrder::Weighted,
+            PlaybackOrder::AvoidCategory,
+            PlaybackOrder::Markov,
+        ] {
+            let transitions = Transitions::default();
+            let mut bag = Vec::new();
+            for current in [0, 5] {
+                assert_eq!(
+                    next_cell(
+                        &grid,
+                        (2, 4),
+                        order,
+                        &transitions,
+                        current,
+                        &mut bag,
+                        &mut rng
+                    ),
+                    2
+                );
+            }
+            assert_eq!(
+                next_cell(&grid, (3, 3), order, &transitions, 3, &mut bag, &mut rng),
+                3
+            );
+        }
+    }
+
+    #[test]
+    fn shuffle_plays_each_cell_once_per_round() {
+        let grid = grid();
+        let steps = walk(PlaybackOrder::Shuffle, &grid, (0, 5), 60);
+        for round in steps.chunks(6) {
+            let mut sorted = round.to_vec();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+        }
+        assert!(steps.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn shuffle_starts_a_new_round_when_only_the_current_cell_is_left() {
+        let grid = grid();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut bag = vec![4];
+        let next = next_cell(
+            &grid,
+            (0, 5),
+            PlaybackOrder::Shuffle,
+            &Transitions::default(),
+            4,
+            &mut bag,
+            &mut rng,
+        );
+        assert_ne!(next, 4);
+        assert!(bag.is_empty());
+    }
+
+    #[test]
+    fn weighted_skips_zero_weights() {
+        let mut grid = grid();
+        for cell in grid.iter_mut().flatten() {
+            cell.weight = 0.0;
+        }
+        grid[4].as_mut().unwrap().weight = 1.0;
+        grid[2] = cell(Category::People, "talk_1");
+        grid[2].as_mut().unwrap().weight = 0.0;
+        let steps = walk(PlaybackOrder::Weighted, &grid, (0, 5), 40);
+        // NOTE: From cell 4 every other weight is zero, so the pick falls back to any other cell
+        for pair in steps.windows(2) {
+            if pair[0] != 4 {
+                assert_eq!(pair[1], 4);
+            } else {
+                assert_ne!(pair[1], 4);
+            }
+        }
+    }
+
+    #[test]
+    fn avoid_category_changes_category_when_it_can() {
+        let grid = grid();
+        let steps = walk(PlaybackOrder::AvoidCategory, &grid, (0, 5), 60);
+        for pair in steps.windows(2) {
+            let category = |idx: usize| grid[idx].as_ref().map(|cell| cell.sample.category);
+            assert!(category(pair[0]).is_none() || category(pair[0]) != category(pair[1]));
+        }
+    }
+
+    #[test]
+    fn markov_follows_the_transition_weights() {
+        let grid = grid();
+        let mut transitions = Transitions::default();
+        for category in Category::iter() {
+            for to in Category::iter() {
+                transitions.set_weight(category, to, 0.0);
+            }
+        }
+        transitions.set_weight(Category::Boom, Category::Doors, 1.0);
+        transitions.set_weight(Category::Doors, Category::Eerie, 1.0);
+        transitions.set_weight(Category::Eerie, Category::Boom, 1.0);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut bag = Vec::new();
+        let mut current = 0;
+        for _ in 0..30 {
+            let next = next_cell(
+                &grid,
+                (0, 5),
+                PlaybackOrder::Markov,
+                &transitions,
+                current,
+                &mut bag,
+                &mut rng,
+            );
+            let from = grid[current].as_ref().unwrap().sample.category;
+            let to = grid[next].as_ref().unwrap().sample.category;
+            assert_eq!(transitions.weight(from, to), 1.0);
+            current = next;
+        }
+    }
+
+    #[test]
+    fn pick_weighted_needs_a_finite_positive_total() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(pick_weighted(&[], &mut rng), None);
+        assert_eq!(pick_weighted(&[0.0, 0.0], &mut rng), None);
+        assert_eq!(pick_weighted(&[1.0, f64::INFINITY], &mut rng), None);
+        assert_eq!(pick_weighted(&[1.0, f64::NAN], &mut rng), None);
+        for _ in 0..20 {
+            assert_eq!(pick_weighted(&[0.0, 2.0, 0.0], &mut rng), Some(1));
+        }
+    }
+
+    #[test]
+    fn gap_samples_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for distribution in GapDistribution::iter() {
+            for _ in 0..1000 {
+                let gap = distribution.sample(0.5, 2.0, &mut rng);
+                assert!((0.5..=2.0).contains(&gap), "{distribution} gave {gap}");
+            }
+            assert_eq!(distribution.sample(1.5, 1.5, &mut rng), 1.5);
+            assert_eq!(distribution.sample(1.5, 1.0, &mut rng), 1.5);
+        }
+    }
+
+    #[test]
+    fn legacy_preset_becomes_one_track() {
+        let json = r#"{
+            "id": "preset_abcd",
+            "name": "Old",
+            "volume": 0.8,
+            "gap_duration": 1500,
+            "random_playback": true,
+            "grid_data": [
+                {
+                    "id": "boom_boom_1",
+                    "filename": "boom_1",
+                    "filepath": "/public/sounds/boom/boom_1.mp3",
+                    "category": "Boom",
+                    "duration": 2.5
+                },
+                null
+            ],
+            "created": "2024-01-01T00:00:00Z"
+        }"#;
+        let preset = serde_json::from_str::<Preset>(json).unwrap();
+        assert_eq!(preset.grid_columns, None);
+        assert_eq!(preset.tracks.len(), 1);
+        let track = &preset.tracks[0];
+        assert_eq!(track.playback_order, PlaybackOrder::Shuffle);
+        assert_eq!(track.gap_range(), (1500, 1500));
+        assert_eq!(track.grid_data.len(), 2);
+        let cell = track.grid_data[0].as_ref().unwrap();
+        assert_eq!(cell.sample.family_name(), "boom");
+        assert_eq!(cell.gain, 1.0);
+        assert_eq!(cell.repeat, 1);
+    }
+
+    #[test]
+    fn track_reads_the_random_playback_flag() {
+        let track = |order: &str| {
+            let json = format!(
+                r#"{{"id": "track_abcd", "grid_data": [], "gap_duration": 1000, "volume": 1.0, {order}}}"#
+            );
+            serde_json::from_str::<Track>(&json).unwrap().playback_order
+        };
+        assert_eq!(track(r#""random_playback": true"#), PlaybackOrder::Shuffle);
+        assert_eq!(
+            track(r#""random_playback": false"#),
+            PlaybackOrder::Sequential
+        );
+        assert_eq!(
+            track(r#""playback_order": "Markov""#),
+            PlaybackOrder::Markov
+        );
+    }
+}
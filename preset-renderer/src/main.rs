@@ -0,0 +1,169 @@
+mod render;
+
+use preset_model::decode::decode;
+use preset_model::{Category, Preset, Sample};
+use render::RenderOptions;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "Usage: preset-renderer <preset.json | store.bin> [options]
+
+Options:
+    --preset <id or name>   Preset to pick when the input is the app store
+    --out <file>            Output WAV file [default: render.wav]
+    --minutes <n>           Render length in minutes [default: 30]
+    --seconds <n>           Render length in seconds, overrides --minutes
+    --seed <n>              Seed for gaps and random order [default: 0]
+    --sample-rate <hz>      Output sample rate [default: 44100]
+    --root <dir>            Directory holding `public/sounds` [default: workspace root]";
+
+struct Args {
+    input: PathBuf,
+    preset: Option<String>,
+    out: PathBuf,
+    seconds: f64,
+    seed: u64,
+    sample_rate: u32,
+    root: PathBuf,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = std::env::args().skip(1);
+    let mut input = None;
+    let mut preset = None;
+    let mut out = PathBuf::from("render.wav");
+    let mut minutes = 30.0;
+    let mut seconds = None;
+    let mut seed = 0;
+    let mut sample_rate = 44100;
+    let mut root = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/.."));
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--preset" => preset = Some(value()?),
+            "--out" => out = PathBuf::from(value()?),
+            "--minutes" => minutes = value()?.parse::<f64>().map_err(|e| e.to_string())?,
+            "--seconds" => seconds = Some(value()?.parse::<f64>().map_err(|e| e.to_string())?),
+            "--seed" => seed = value()?.parse::<u64>().map_err(|e| e.to_string())?,
+            "--sample-rate" => sample_rate = value()?.parse::<u32>().map_err(|e| e.to_string())?,
+            "--root" => root = PathBuf::from(value()?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if input.is_none() && !arg.starts_with("--") => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unknown argument {arg}\n\n{USAGE}")),
+        }
+    }
+
+    Ok(Args {
+        input: input.ok_or(USAGE.to_string())?,
+        preset,
+        out,
+        seconds: seconds.unwrap_or(minutes * 60.0),
+        seed,
+        sample_rate,
+        root,
+    })
+}
+
+// NOTE: Accepts a bare preset or the app store, where presets are JSON strings under
+// `preset_*` keys
+fn read_preset(args: &Args) -> Result<Preset, Box<dyn Error>> {
+    let json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&args.input)?)?;
+
+    if json.get("tracks").is_some() || json.get("grid_data").is_some() {
+        return Ok(serde_json::from_value(json)?);
+    }
+
+    let presets = json
+        .as_object()
+        .ok_or("Input is neither a preset nor a store")?
+        .iter()
+        .filter(|(key, _)| key.starts_with("preset_"))
+        .filter_map(|(_, value)| serde_json::from_str::<Preset>(value.as_str()?).ok())
+        .collect::<Vec<_>>();
+
+    match &args.preset {
+        Some(wanted) => presets
+            .into_iter()
+            .find(|preset| &preset.id == wanted || &preset.name == wanted)
+            .ok_or_else(|| format!("No preset {wanted} in the store").into()),
+        None => {
+            let names = presets
+                .iter()
+                .map(|preset| format!("  {} ({})", preset.name, preset.id))
+                .collect::<Vec<_>>();
+            Err(format!("Pick a preset with --preset:\n{}", names.join("\n")).into())
+        }
+    }
+}
+
+// NOTE: Without the library every variant or category cell plays its own sample
+fn read_library(root: &Path) -> Result<HashMap<Category, Vec<Sample>>, Box<dyn Error>> {
+    match fs::read_to_string(root.join("public/sounds/lib.json")) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+    let preset = read_preset(&args)?;
+    let library = read_library(&args.root)?;
+
+    let mut buffers = HashMap::new();
+    for sample in preset
+        .tracks
+        .iter()
+        .flat_map(|track| track.grid_data.iter().flatten())
+        .flat_map(|cell| cell.pool_members(&library))
+    {
+        let filepath = &sample.filepath;
+        if !buffers.contains_key(filepath) {
+            let path = args.root.join(filepath.trim_start_matches('/'));
+            buffers.insert(filepath.clone(), decode(&path)?);
+        }
+    }
+
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: args.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&args.out, spec)?;
+    let opts = RenderOptions {
+        seconds: args.seconds,
+        sample_rate: args.sample_rate,
+        seed: args.seed,
+    };
+
+    render::render(&preset, &library, &buffers, &opts, |block| {
+        for sample in block {
+            writer.write_sample((sample * f32::from(i16::MAX)) as i16)?;
+        }
+        Ok(())
+    })?;
+    writer.finalize()?;
+
+    println!(
+        "Rendered {} ({:.0}s, seed {}) to {}",
+        preset.name,
+        args.seconds,
+        args.seed,
+        args.out.display()
+    );
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
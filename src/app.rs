@@ -1,23 +1,24 @@
 use crate::components::{
-    control_panel::ControlPanel, grid::Grid, presets::Presets, schedule::Schedule,
-    settings_menu::SettingsMenu, sound_library::SoundLibrary,
+    control_panel::ControlPanel, equalizer::EqualizerPanel, generator::GeneratorPanel, grid::Grid,
+    presets::Presets, schedule::Schedule, selection_bar::SelectionBar, settings_menu::SettingsMenu,
+    sound_library::SoundLibrary, tracks::TrackBar, transitions::TransitionsPanel,
 };
+use crate::playback::engine::PlaybackEngine;
 use crate::shared::{
-    grid_row_size, Category, Operation, PlannedSchedule, Preset, RecurringSchedule, Sample,
-    ScheduleType, EMPTY_SOUND, GRID_ROWS_MAX, GRID_ROWS_MIN, SOUND_LIB_JSON_PATH, SOUND_LIB_PATH,
+    clamp_grid_columns, fit_grid, grid_row_size, move_cells, Category, Cell, Channel, Clipboard,
+    EditKey, Envelope, Equalizer, GapDistribution, History, Humanize, Limiter, Mixer, Operation,
+    PatternSpec, PlannedSchedule, PlaybackOrder, PlaybackState, Preset, RecurringSchedule, RowOp,
+    Sample, ScheduleType, SelectMode, Selection, Snapshot, TimingMode, Track, Transitions,
+    GRID_ROWS_MAX, GRID_ROWS_MIN, SOUND_LIB_JSON_PATH, TRACKS_MAX,
 };
 use chrono::{Datelike, Local, Utc};
-use html::Audio;
 use leptos::{error::ErrorBoundary, prelude::*, *};
-use leptos_dom::helpers::TimeoutHandle;
 use leptos_use::{
     use_timestamp_with_controls_and_options, UseTimestampOptions, UseTimestampReturn,
 };
 use rand::distr::{Alphanumeric, SampleString};
-use rand::{rng, Rng};
 use std::collections::HashMap;
-use std::time::Duration;
-use wasm_bindgen::{closure::Closure, prelude::*};
+use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::Response;
 
@@ -61,15 +62,31 @@ extern "C" {
 
 #[component]
 pub fn App() -> impl IntoView {
-    let (grid_data, set_grid_data) = signal::<Vec<Option<Sample>>>(Vec::new());
+    let (tracks, set_tracks) = signal::<Vec<Track>>(Vec::new());
+    let (active_track, set_active_track) = signal(0);
     let (play, set_play) = signal(false);
-    let (gap_duration, set_gap_duration) = signal(1000);
-    let (current_cell, set_current_cell) = signal(0);
-    let (timeout_handler, set_timeout_handler) = signal::<Option<TimeoutHandle>>(None);
+    let (current_cells, set_current_cells) = signal::<Vec<usize>>(Vec::new());
     let (volume, set_volume) = signal::<f32>(1.0);
+    let (envelope, set_envelope) = signal(Envelope::default());
+    let (humanize, set_humanize) = signal(Humanize::default());
+    let (equalizer, set_equalizer) = signal(Equalizer::default());
+    let (equalizer_visible, set_equalizer_visible) = signal(false);
+    let (limiter, set_limiter) = signal(Limiter::default());
+    let (transitions, set_transitions) = signal(Transitions::default());
+    let (transitions_visible, set_transitions_visible) = signal(false);
+    let (generator_visible, set_generator_visible) = signal(false);
+    let (mixer, set_mixer) = signal(Mixer::default());
+    let (grid_columns, set_grid_columns) = signal(grid_row_size());
     let (edit_cell_idx, set_edit_cell_idx) = signal::<Option<u16>>(None);
-    let (random_playback, set_random_playback) = signal(false);
+    let (loop_select, set_loop_select) = signal(false);
+    let (loop_anchor, set_loop_anchor) = signal::<Option<u16>>(None);
     let (save_blocked, set_save_blocked) = signal(false);
+    let (selection, set_selection) = signal(Selection::default());
+    let (clipboard, set_clipboard) = signal(Clipboard::default());
+    let (history, set_history) = signal(History::default());
+    // NOTE: State as of the last recorded change, none until the session is restored
+    let last_snapshot = StoredValue::new(None::<Snapshot>);
+    let last_edit = StoredValue::new(None::<(EditKey, f64)>);
     let (presets_visible, set_presets_visible) = signal(false);
     let (presets, set_presets) = signal::<Vec<Preset>>(Vec::new());
     let (scheduled_playback, set_scheduled_playback) = signal(false);
@@ -102,8 +119,129 @@ pub fn App() -> impl IntoView {
             .collect()
     });
 
-    let main_audio_elem_ref = NodeRef::<Audio>::new();
-    let secondary_audio_elem_ref = NodeRef::<Audio>::new();
+    let (playback_state, set_playback_state) = signal(PlaybackState::Idle);
+
+    let engine = StoredValue::new_local(PlaybackEngine::new(
+        move |track, cell| {
+            set_current_cells.update(|cells| {
+                if cells.len() <= track {
+                    cells.resize(track + 1, 0);
+                }
+                cells[track] = cell;
+            });
+        },
+        move |state| set_playback_state(state),
+    ));
+
+    // NOTE: Grid, gap and playback order settings shown in the UI belong to the active track
+    let grid_data = Memo::new(move |_| {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.grid_data.clone())
+                .unwrap_or_default()
+        })
+    });
+    let gap_duration = Signal::derive(move || {
+        tracks.with(|t| t.get(active_track()).map_or(0, |track| track.gap_duration))
+    });
+    let gap_max = Signal::derive(move || {
+        tracks.with(|t| t.get(active_track()).map_or(0, |track| track.gap_range().1))
+    });
+    let gap_distribution = Signal::derive(move || {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.gap_distribution)
+                .unwrap_or_default()
+        })
+    });
+    let playback_order = Signal::derive(move || {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.playback_order)
+                .unwrap_or_default()
+        })
+    });
+
+    let timing = Signal::derive(move || {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.timing)
+                .unwrap_or_default()
+        })
+    });
+    let step_interval = Signal::derive(move || {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.step_interval)
+                .unwrap_or_default()
+        })
+    });
+
+    let set_grid_data = move |grid_data: Vec<Option<Cell>>| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.grid_data = grid_data;
+        });
+    };
+    let set_gap_duration = Callback::new(move |gap_duration: u64| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.gap_duration = gap_duration;
+            track.gap_max = track.gap_max.max(gap_duration);
+        });
+    });
+    let set_gap_max = Callback::new(move |gap_max: u64| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.gap_max = gap_max;
+            track.gap_duration = track.gap_duration.min(gap_max);
+        });
+    });
+    let set_gap_distribution = Callback::new(move |gap_distribution: GapDistribution| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.gap_distribution = gap_distribution;
+        });
+    });
+    let set_timing = Callback::new(move |timing: TimingMode| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.timing = timing;
+        });
+    });
+    let set_step_interval = Callback::new(move |step_interval: u64| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.step_interval = step_interval;
+        });
+    });
+    let set_playback_order = Callback::new(move |playback_order: PlaybackOrder| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.playback_order = playback_order;
+        });
+    });
+
+    let track_channel = Signal::derive(move || {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.channel)
+                .unwrap_or_default()
+        })
+    });
+    let set_track_channel = Callback::new(move |channel: Channel| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.channel = channel;
+        });
+    });
+
+    let loop_range = Signal::derive(move || {
+        tracks.with(|t| t.get(active_track()).and_then(|track| track.loop_range))
+    });
+
+    let loop_select_handler = Callback::new(move |_| {
+        set_loop_anchor(None);
+        set_loop_select.update(|val| *val = !*val);
+    });
+
+    let clear_loop_handler = Callback::new(move |_| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.loop_range = None;
+        });
+    });
 
     let UseTimestampReturn {
         timestamp,
@@ -115,17 +253,31 @@ pub fn App() -> impl IntoView {
     // NOTE: moved here in order to use the closure in scheduled playback effect
     let load_preset_handler = Callback::new(move |preset: Preset| {
         let Preset {
-            gap_duration,
             volume,
-            random_playback,
-            grid_data,
+            mut tracks,
+            envelope,
+            equalizer,
+            humanize,
+            transitions,
+            mixer,
+            grid_columns,
             ..
         } = preset;
+        let grid_columns = grid_columns.unwrap_or_else(grid_row_size);
+        for track in tracks.iter_mut() {
+            fit_grid(&mut track.grid_data, grid_columns);
+        }
 
-        set_gap_duration(gap_duration);
         set_volume(volume);
-        set_random_playback(random_playback);
-        set_grid_data(grid_data);
+        set_envelope(envelope);
+        set_equalizer(equalizer);
+        set_humanize(humanize);
+        set_transitions(transitions);
+        set_mixer(mixer);
+        set_grid_columns(grid_columns);
+        set_current_cells(vec![0; tracks.len()]);
+        set_active_track(0);
+        set_tracks(tracks);
 
         set_presets_visible(false);
     });
@@ -138,44 +290,127 @@ pub fn App() -> impl IntoView {
             let store = load("store.bin").await;
 
             // store.clear().await;
-            if let Ok(gap_duration_js_val) =
-                serde_wasm_bindgen::from_value::<String>(store.get("duration").await)
+            if let Ok(volume_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("volume").await)
             {
-                if let Ok(gap_duration) = gap_duration_js_val.parse::<u64>() {
-                    set_gap_duration(gap_duration);
+                if let Ok(volume) = volume_js_val.parse::<f32>() {
+                    set_volume(volume);
                 }
             }
 
-            if let Ok(random_playback) = serde_wasm_bindgen::from_value::<String>(
-                store.get("random_playback").await,
-            )
-            .map(|val| match val.as_str() {
-                "true" => true,
-                "false" => false,
-                _ => false,
-            }) {
-                set_random_playback(random_playback);
+            if let Ok(envelope_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("envelope").await)
+            {
+                if let Ok(envelope) = serde_json::from_str::<Envelope>(envelope_js_val.as_str()) {
+                    set_envelope(envelope);
+                }
             }
 
-            if let Ok(volume_js_val) =
-                serde_wasm_bindgen::from_value::<String>(store.get("volume").await)
+            if let Ok(humanize_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("humanize").await)
             {
-                if let Ok(volume) = volume_js_val.parse::<f32>() {
-                    set_volume(volume);
+                if let Ok(humanize) = serde_json::from_str::<Humanize>(humanize_js_val.as_str()) {
+                    set_humanize(humanize);
+                }
+            }
+
+            if let Ok(equalizer_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("equalizer").await)
+            {
+                if let Ok(equalizer) = serde_json::from_str::<Equalizer>(equalizer_js_val.as_str())
+                {
+                    set_equalizer(equalizer);
+                }
+            }
+
+            if let Ok(limiter_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("limiter").await)
+            {
+                if let Ok(limiter) = serde_json::from_str::<Limiter>(limiter_js_val.as_str()) {
+                    set_limiter(limiter);
+                }
+            }
+
+            if let Ok(transitions_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("transitions").await)
+            {
+                if let Ok(transitions) =
+                    serde_json::from_str::<Transitions>(transitions_js_val.as_str())
+                {
+                    set_transitions(transitions);
+                }
+            }
+
+            if let Ok(mixer_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("mixer").await)
+            {
+                if let Ok(mixer) = serde_json::from_str::<Mixer>(mixer_js_val.as_str()) {
+                    set_mixer(mixer);
+                }
+            }
+
+            let mut columns = grid_row_size();
+            if let Ok(columns_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("grid_columns").await)
+            {
+                if let Ok(stored_columns) = columns_js_val.parse::<u16>() {
+                    columns = clamp_grid_columns(stored_columns);
                 }
             }
+            set_grid_columns(columns);
 
-            if let Ok(grid_data_js_val) =
-                serde_wasm_bindgen::from_value::<String>(store.get("grid_data").await)
+            if let Ok(history_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("history").await)
             {
-                let grid_data =
-                    serde_json::from_str::<Vec<Option<Sample>>>(grid_data_js_val.as_str());
+                if let Ok(history) = serde_json::from_str::<History>(history_js_val.as_str()) {
+                    set_history(history);
+                }
+            }
+
+            if let Ok(tracks_js_val) =
+                serde_wasm_bindgen::from_value::<String>(store.get("tracks").await)
+            {
+                let mut tracks =
+                    serde_json::from_str::<Vec<Track>>(tracks_js_val.as_str()).unwrap();
+                for track in tracks.iter_mut() {
+                    fit_grid(&mut track.grid_data, columns);
+                }
 
-                set_grid_data(grid_data.unwrap());
+                set_tracks(tracks);
             } else {
-                let mut grid_data_initial = vec![None; usize::from(grid_row_size() * 2)];
-                fill_grid_initial(&mut grid_data_initial);
-                set_grid_data(grid_data_initial);
+                // NOTE: Sessions saved before tracks existed hold a single sequence
+                let mut track = Track::new(Vec::new());
+
+                if let Ok(gap_duration_js_val) =
+                    serde_wasm_bindgen::from_value::<String>(store.get("duration").await)
+                {
+                    if let Ok(gap_duration) = gap_duration_js_val.parse::<u64>() {
+                        track.gap_duration = gap_duration;
+                        track.gap_max = gap_duration;
+                    }
+                }
+
+                if let Ok(random_playback_js_val) =
+                    serde_wasm_bindgen::from_value::<String>(store.get("random_playback").await)
+                {
+                    track.playback_order = PlaybackOrder::from(random_playback_js_val == "true");
+                }
+
+                if let Ok(grid_data_js_val) =
+                    serde_wasm_bindgen::from_value::<String>(store.get("grid_data").await)
+                {
+                    let grid_data =
+                        serde_json::from_str::<Vec<Option<Cell>>>(grid_data_js_val.as_str());
+
+                    track.grid_data = grid_data.unwrap();
+                    fit_grid(&mut track.grid_data, columns);
+                } else {
+                    // NOTE: New users start from an empty grid and the pattern generator
+                    track.grid_data = vec![None; usize::from(columns * 2)];
+                    set_generator_visible(true);
+                }
+
+                set_tracks(vec![track]);
             }
 
             set_save_blocked(false);
@@ -187,33 +422,116 @@ pub fn App() -> impl IntoView {
         if save_blocked() {
             return;
         };
-        let l_duration = gap_duration().to_string();
         let l_volume = volume().to_string();
-        let l_random = random_playback().to_string();
-        let l_grid_data = serde_json::to_string(&grid_data()).unwrap();
+        let l_tracks = serde_json::to_string(&tracks()).unwrap();
+        let l_envelope = serde_json::to_string(&envelope()).unwrap();
+        let l_equalizer = serde_json::to_string(&equalizer()).unwrap();
+        let l_humanize = serde_json::to_string(&humanize()).unwrap();
+        let l_transitions = serde_json::to_string(&transitions()).unwrap();
+        let l_limiter = serde_json::to_string(&limiter()).unwrap();
+        let l_mixer = serde_json::to_string(&mixer()).unwrap();
+        let l_grid_columns = grid_columns().to_string();
 
         wasm_bindgen_futures::spawn_local(async move {
             let store = load("store.bin").await;
 
-            store.set("duration", l_duration.as_str()).await;
             store.set("volume", l_volume.as_str()).await;
-            store.set("random_playback", l_random.as_str()).await;
-            store.set("grid_data", l_grid_data.as_str()).await;
+            store.set("tracks", l_tracks.as_str()).await;
+            store.set("envelope", l_envelope.as_str()).await;
+            store.set("equalizer", l_equalizer.as_str()).await;
+            store.set("humanize", l_humanize.as_str()).await;
+            store.set("transitions", l_transitions.as_str()).await;
+            store.set("limiter", l_limiter.as_str()).await;
+            store.set("mixer", l_mixer.as_str()).await;
+            store.set("grid_columns", l_grid_columns.as_str()).await;
             store.save().await;
         });
     });
 
+    // NOTE: The history is saved apart from the rest and only its latest steps, so a slider
+    // drag does not rewrite the full stacks on every tick
+    Effect::new(move |_| {
+        if save_blocked() {
+            return;
+        };
+        let l_history = history.with(|h| serde_json::to_string(&h.persisted()).unwrap());
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let store = load("store.bin").await;
+
+            store.set("history", l_history.as_str()).await;
+            store.save().await;
+        });
+    });
+
+    // NOTE: Record the state before every change of the tracks or the master volume. Undo
+    // and redo move the last snapshot first, so stepping through history is not recorded
+    Effect::new(move |_| {
+        let current = Snapshot {
+            tracks: tracks(),
+            volume: volume(),
+            columns: grid_columns(),
+        };
+        if save_blocked() {
+            last_snapshot.set_value(None);
+            return;
+        }
+
+        if let Some(previous) = last_snapshot.get_value() {
+            if previous != current {
+                let key = EditKey::between(&previous, &current);
+                let now = js_sys::Date::now();
+                let is_burst = last_edit.with_value(|last| key.continues(last, now));
+                last_edit.set_value(Some((key, now)));
+                if !is_burst {
+                    set_history.update(|h| h.record(previous));
+                }
+            }
+        }
+        last_snapshot.set_value(Some(current));
+    });
+
+    let apply_snapshot = move |snapshot: Snapshot| {
+        last_snapshot.set_value(Some(snapshot.clone()));
+        last_edit.set_value(None);
+        let tracks_num = snapshot.tracks.len();
+        set_edit_cell_idx(None);
+        set_current_cells.update(|cells| cells.resize(tracks_num, 0));
+        if active_track.get_untracked() >= tracks_num {
+            set_active_track(tracks_num.saturating_sub(1));
+        }
+        set_volume(snapshot.volume);
+        set_grid_columns(snapshot.columns);
+        set_tracks(snapshot.tracks);
+    };
+
+    let current_snapshot = move || Snapshot {
+        tracks: tracks.get_untracked(),
+        volume: volume.get_untracked(),
+        columns: grid_columns.get_untracked(),
+    };
+
+    let undo_handler = Callback::new(move |_| {
+        let mut h = history.get_untracked();
+        if let Some(snapshot) = h.undo(current_snapshot()) {
+            set_history(h);
+            apply_snapshot(snapshot);
+        }
+    });
+
+    let redo_handler = Callback::new(move |_| {
+        let mut h = history.get_untracked();
+        if let Some(snapshot) = h.redo(current_snapshot()) {
+            set_history(h);
+            apply_snapshot(snapshot);
+        }
+    });
+
     // NOTE: Stop playing grid preview when opening sound lib
     Effect::watch(
         edit_cell_idx,
         move |_, _, _| {
-            let secondary_audio_elem = secondary_audio_elem_ref
-                .get()
-                .expect("Failed to get ref to secondary audio element");
-            if !secondary_audio_elem.paused() {
-                let _ = secondary_audio_elem.pause();
-                secondary_audio_elem.set_current_time(0.0);
-            }
+            engine.with_value(|e| e.stop_preview());
         },
         true,
     );
@@ -243,50 +561,60 @@ pub fn App() -> impl IntoView {
         });
     });
 
-    // NOTE: Play grid
     Effect::new(move |_| {
-        let main_audio_elem = main_audio_elem_ref
-            .get()
-            .expect("Failed to get ref to main audio element");
-        let secondary_audio_elem = secondary_audio_elem_ref
-            .get()
-            .expect("Failed to get ref to secondary audio element");
+        if let Some(lib) = sound_lib.get() {
+            engine.with_value(|e| e.set_library(lib));
+        }
+    });
 
-        if play() {
-            let _ = secondary_audio_elem.pause();
-            secondary_audio_elem.set_current_time(0.0);
+    // NOTE: Keep the engine in sync with the tracks
+    Effect::new(move |_| {
+        let tracks = tracks();
+        engine.with_value(|e| e.set_tracks(tracks));
+    });
 
-            if let Some(sample_opt) = grid_data().get(current_cell()) {
-                if let Some(sample) = sample_opt {
-                    main_audio_elem.set_src(&sample.filepath);
+    Effect::new(move |_| {
+        let volume = volume();
+        engine.with_value(|e| e.set_volume(volume));
+    });
 
-                    if let Ok(promise) = main_audio_elem.play() {
-                        let reject_handler = Closure::new(move |err| {
-                            logging::error!("{:?}", err);
-                        });
-                        let _ = promise.catch(&reject_handler);
-                        reject_handler.forget();
-                    }
-                } else {
-                    main_audio_elem.set_src(EMPTY_SOUND);
-                    if let Ok(promise) = main_audio_elem.play() {
-                        let reject_handler = Closure::new(move |err| {
-                            logging::error!("{:?}", err);
-                        });
-                        let _ = promise.catch(&reject_handler);
-                        reject_handler.forget();
-                    }
-                }
-            } else {
-                set_current_cell(0);
-            }
+    Effect::new(move |_| {
+        let equalizer = equalizer();
+        engine.with_value(|e| e.set_equalizer(&equalizer));
+    });
+
+    Effect::new(move |_| {
+        let limiter = limiter();
+        engine.with_value(|e| e.set_limiter(&limiter));
+    });
+
+    Effect::new(move |_| {
+        let envelope = envelope();
+        engine.with_value(|e| e.set_envelope(envelope));
+    });
+
+    Effect::new(move |_| {
+        let humanize = humanize();
+        engine.with_value(|e| e.set_humanize(humanize));
+    });
+
+    Effect::new(move |_| {
+        let transitions = transitions();
+        engine.with_value(|e| e.set_transitions(transitions));
+    });
+
+    Effect::new(move |_| {
+        let mixer = mixer();
+        engine.with_value(|e| e.set_mixer(mixer));
+    });
+
+    // NOTE: Play grid
+    Effect::new(move |_| {
+        if play() {
+            let cells = current_cells.get_untracked();
+            engine.with_value(|e| e.play(&cells));
         } else {
-            let _ = main_audio_elem.pause();
-            main_audio_elem.set_current_time(0.0);
-            if let Some(timeout_handle) = timeout_handler() {
-                timeout_handle.clear();
-                set_timeout_handler(None);
-            }
+            engine.with_value(|e| e.stop());
         }
     });
 
@@ -370,96 +698,183 @@ pub fn App() -> impl IntoView {
     });
 
     let grid_size_handler = Callback::new(move |op: Operation| {
-        let mut gd = grid_data();
+        let mut gd = grid_data.get_untracked();
         let len = gd.len();
+        let columns = grid_columns.get_untracked();
+        let rows = len / columns as usize;
 
-        // NOTE: Don't do anything if restriction boundaries are reached
-        if op == Operation::Dec && len as u16 == GRID_ROWS_MIN * grid_row_size()
-            || op == Operation::Inc && len as u16 == grid_row_size() * GRID_ROWS_MAX
+        // NOTE: Don't do anything if restriction boundaries are reached, the same bounds the
+        // row operations keep
+        if op == Operation::Dec && rows <= GRID_ROWS_MIN as usize
+            || op == Operation::Inc && rows >= GRID_ROWS_MAX as usize
         {
             return;
         }
 
         set_grid_data(match op {
             Operation::Dec => {
-                gd.drain(len - grid_row_size() as usize..);
+                gd.drain(len - columns as usize..);
                 gd
             }
             Operation::Inc => {
-                gd.splice(len.., vec![None; grid_row_size() as usize]);
+                gd.splice(len.., vec![None; columns as usize]);
                 gd
             }
         });
     });
 
-    let ended_listener = move |_| {
-        let handler = set_timeout_with_handle(
-            move || {
-                set_current_cell.update(move |val| {
-                    let len = grid_data().len();
-                    *val = if random_playback() {
-                        // NOTE: Exclude possibility of duplicating random index
-                        let current = rng().random_range(0..len);
-                        if *val != current {
-                            current
-                        } else {
-                            let middle = len / 2;
-                            if *val > middle {
-                                rng().random_range(0..(*val - 1))
-                            } else {
-                                rng().random_range((*val + 1)..len)
-                            }
-                        }
-                    } else if *val == len - 1 {
-                        0
-                    } else {
-                        *val + 1
-                    }
-                })
-            },
-            Duration::from_millis(gap_duration()),
-        )
-        .ok();
+    // NOTE: The loop, current cell and selection follow the rows they were in
+    let row_handler = Callback::new(move |(row, op): (usize, RowOp)| {
+        let row_size = grid_columns.get_untracked() as usize;
+        let mut gd = grid_data.get_untracked();
+        if !op.apply(&mut gd, row, row_size) {
+            return;
+        }
 
-        set_timeout_handler(handler);
-    };
+        let last = gd.len() - 1;
+        let track = active_track.get_untracked();
+        update_track(set_tracks, track, |t| {
+            t.grid_data = gd;
+            t.loop_range = t.loop_range.and_then(|(start, end)| {
+                let start = op.remap(start, row, row_size)?;
+                let end = op.remap(end, row, row_size)?;
+                Some((start.min(end), start.max(end)))
+            });
+        });
+        set_current_cells.update(|cells| {
+            if let Some(cell) = cells.get_mut(track) {
+                *cell = op
+                    .remap(*cell, row, row_size)
+                    .unwrap_or((row * row_size).min(last));
+            }
+        });
+        engine.with_value(|e| e.remap(track, op, row, row_size));
+        set_selection.update(|s| {
+            s.set(
+                s.cells()
+                    .iter()
+                    .filter_map(|&idx| op.remap(idx, row, row_size))
+                    .collect(),
+            )
+        });
+    });
+
+    let add_track_handler = Callback::new(move |_: ev::MouseEvent| {
+        if tracks.with_untracked(|t| t.len()) >= TRACKS_MAX {
+            return;
+        }
+        let idx = tracks.with_untracked(|t| t.len());
+        set_tracks.update(|t| {
+            t.push(Track::new(vec![
+                None;
+                usize::from(
+                    grid_columns.get_untracked() * 2
+                )
+            ]));
+        });
+        set_active_track(idx);
+    });
+
+    let remove_track_handler = Callback::new(move |idx: usize| {
+        if tracks.with_untracked(|t| t.len()) <= 1 {
+            return;
+        }
+        set_current_cells.update(|cells| {
+            if idx < cells.len() {
+                cells.remove(idx);
+            }
+        });
+        set_tracks.update(|t| {
+            t.remove(idx);
+        });
+        if active_track.get_untracked() >= idx && active_track.get_untracked() > 0 {
+            set_active_track.update(|active| *active -= 1);
+        }
+    });
+
+    let track_volume_handler = Callback::new(move |volume: f32| {
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.volume = volume;
+        });
+    });
+
+    // NOTE: `play` starts and stops the sequence, pausing keeps it where it is
+    let play_handler = Callback::new(move |_| match playback_state.get_untracked() {
+        PlaybackState::Idle => set_play(true),
+        PlaybackState::Playing | PlaybackState::InGap => engine.with_value(|e| e.pause()),
+        PlaybackState::Paused => engine.with_value(|e| e.resume()),
+    });
+
+    let stop_handler = Callback::new(move |_| set_play(false));
+
+    let skip_handler = Callback::new(move |forward: bool| {
+        engine.with_value(|e| e.skip(forward));
+    });
 
     let grid_cell_click_handler =
         Callback::new(move |(sound_url_opt, idx): (Option<String>, u16)| {
-            if !play.get() {
+            if playback_state.get_untracked() == PlaybackState::Idle {
                 if let Some(sound_url) = sound_url_opt {
-                    let audio = secondary_audio_elem_ref
-                        .get()
-                        .expect("Failed to get ref to secondary audio element");
-
-                    if !audio.paused() && audio.src().contains(&sound_url) {
-                        let _ = audio.pause();
-                        audio.set_current_time(0.0);
-                    } else {
-                        audio.set_src(&sound_url);
-
-                        if let Ok(promise) = audio.play() {
-                            let reject_handler = Closure::new(move |err| {
-                                logging::error!("{:?}", err);
-                            });
-                            let _ = promise.catch(&reject_handler);
-                            reject_handler.forget();
-                        }
+                    engine.with_value(|e| e.preview(&sound_url));
+                }
+            }
+
+            let track = active_track.get_untracked();
+
+            // NOTE: While picking a loop the first click sets one end and the second the other
+            if loop_select.get_untracked() {
+                match loop_anchor.get_untracked() {
+                    None => set_loop_anchor(Some(idx)),
+                    Some(anchor) => {
+                        update_track(set_tracks, track, |t| {
+                            t.loop_range =
+                                Some((anchor.min(idx) as usize, anchor.max(idx) as usize));
+                        });
+                        set_loop_anchor(None);
+                        set_loop_select(false);
                     }
                 }
             }
 
-            set_current_cell.set(idx as usize);
+            set_current_cells.update(|cells| {
+                if cells.len() <= track {
+                    cells.resize(track + 1, 0);
+                }
+                cells[track] = idx as usize;
+            });
+            engine.with_value(|e| e.seek(track, idx as usize));
+            // NOTE: A plain click drops the selection and anchors the next Shift+click
+            set_selection.update(|s| s.select(idx as usize, SelectMode::Replace));
         });
 
     let sample_select_handler = Callback::new(move |sample| {
         let idx = edit_cell_idx().unwrap();
         set_edit_cell_idx(None);
-        let mut mut_grid_data = grid_data();
-        mut_grid_data[idx as usize] = Some(sample);
+        let mut mut_grid_data = grid_data.get_untracked();
+        // NOTE: Keep the cell settings when swapping the sample, trim belongs to the old one
+        mut_grid_data[idx as usize] = Some(match mut_grid_data[idx as usize].take() {
+            Some(cell) => Cell {
+                sample,
+                trim_start: 0.0,
+                trim_end: 0.0,
+                ..cell
+            },
+            None => Cell::new(sample),
+        });
         set_grid_data(mut_grid_data);
     });
 
+    let cell_change_handler = Callback::new(move |cell: Cell| {
+        let Some(idx) = edit_cell_idx.get_untracked() else {
+            return;
+        };
+        let mut mut_grid_data = grid_data.get_untracked();
+        if let Some(item) = mut_grid_data.get_mut(idx as usize) {
+            *item = Some(cell);
+            set_grid_data(mut_grid_data);
+        }
+    });
+
     let open_library_handler = Callback::new(move |idx| {
         set_edit_cell_idx(Some(idx));
     });
@@ -471,11 +886,131 @@ pub fn App() -> impl IntoView {
     let clear_cell_handler = Callback::new(move |_| {
         let idx = edit_cell_idx().unwrap();
         set_edit_cell_idx(None);
-        let mut mut_grid_data = grid_data();
+        let mut mut_grid_data = grid_data.get_untracked();
         mut_grid_data[idx as usize] = None;
         set_grid_data(mut_grid_data);
     });
 
+    // NOTE: A selection belongs to the grid it was made in
+    Effect::watch(
+        active_track,
+        move |_, _, _| set_selection.update(|s| s.clear()),
+        false,
+    );
+
+    let select_handler = Callback::new(move |(idx, mode): (u16, SelectMode)| {
+        set_selection.update(|s| s.select(idx as usize, mode));
+    });
+
+    // NOTE: Without a selection the clipboard actions work on the current cell
+    let target_cells = move || {
+        let len = grid_data.with_untracked(|gd| gd.len());
+        let cells = selection.with_untracked(|s| {
+            s.cells()
+                .iter()
+                .copied()
+                .filter(|&idx| idx < len)
+                .collect::<Vec<_>>()
+        });
+        if cells.is_empty() {
+            current_cells
+                .with_untracked(|c| c.get(active_track.get_untracked()).copied())
+                .filter(|&idx| idx < len)
+                .into_iter()
+                .collect()
+        } else {
+            cells
+        }
+    };
+
+    let copy_handler = Callback::new(move |_| {
+        let clip = grid_data.with_untracked(|gd| Clipboard::copy(gd, &target_cells()));
+        set_clipboard(clip);
+    });
+
+    let paste_handler = Callback::new(move |_| {
+        let Some(&at) = target_cells().first() else {
+            return;
+        };
+        let mut mut_grid_data = grid_data.get_untracked();
+        let pasted = clipboard.with_untracked(|clip| clip.paste(&mut mut_grid_data, at));
+        set_grid_data(mut_grid_data);
+        set_selection.update(|s| s.set(pasted));
+    });
+
+    let duplicate_handler = Callback::new(move |_| {
+        let cells = target_cells();
+        let Some(&last) = cells.last() else {
+            return;
+        };
+        let mut mut_grid_data = grid_data.get_untracked();
+        let pasted = Clipboard::copy(&mut_grid_data, &cells).paste(&mut mut_grid_data, last + 1);
+        set_grid_data(mut_grid_data);
+        set_selection.update(|s| s.set(pasted));
+    });
+
+    let clear_selection_handler = Callback::new(move |_| {
+        let mut mut_grid_data = grid_data.get_untracked();
+        for idx in target_cells() {
+            mut_grid_data[idx] = None;
+        }
+        set_grid_data(mut_grid_data);
+    });
+
+    let deselect_handler = Callback::new(move |_| {
+        set_selection.update(|s| s.clear());
+    });
+
+    // NOTE: Dragging a selected cell takes the whole selection along
+    let move_handler = Callback::new(move |(from, to): (u16, u16)| {
+        let (from, to) = (from as usize, to as usize);
+        let cells = if selection.with_untracked(|s| s.contains(from)) {
+            selection.with_untracked(|s| s.cells().to_vec())
+        } else {
+            vec![from]
+        };
+        let delta = to as isize - from as isize;
+        let mut mut_grid_data = grid_data.get_untracked();
+        if move_cells(&mut mut_grid_data, &cells, delta) {
+            set_grid_data(mut_grid_data);
+            if cells.len() > 1 {
+                set_selection.update(|s| {
+                    s.set(
+                        cells
+                            .iter()
+                            .map(|&idx| idx.saturating_add_signed(delta))
+                            .collect(),
+                    )
+                });
+            }
+        }
+    });
+
+    // NOTE: Ctrl on Linux and Windows, Cmd on macOS. Text fields keep their own shortcuts
+    let _ = window_event_listener(ev::keydown, move |e| {
+        if e.alt_key() || edit_cell_idx.get_untracked().is_some() {
+            return;
+        }
+        let tag = event_target::<web_sys::Element>(&e).tag_name();
+        if matches!(tag.as_str(), "INPUT" | "TEXTAREA" | "SELECT") {
+            return;
+        }
+
+        let has_selection = selection.with_untracked(|s| !s.is_empty());
+        let ctrl = e.ctrl_key() || e.meta_key();
+        match (e.key().to_lowercase().as_str(), ctrl, e.shift_key()) {
+            ("z", true, false) => undo_handler.run(()),
+            ("z", true, true) | ("y", true, false) => redo_handler.run(()),
+            ("c", true, false) => copy_handler.run(()),
+            ("v", true, false) => paste_handler.run(()),
+            ("d", true, false) => duplicate_handler.run(()),
+            ("delete" | "backspace", false, _) if has_selection => clear_selection_handler.run(()),
+            ("escape", false, _) if has_selection => deselect_handler.run(()),
+            _ => return,
+        }
+        e.prevent_default();
+    });
+
     let save_preset_handler = Callback::new(move |preset_name: String| {
         let time = Utc::now();
         let preset = Preset {
@@ -486,9 +1021,13 @@ pub fn App() -> impl IntoView {
                 preset_name
             },
             volume: volume(),
-            gap_duration: gap_duration(),
-            random_playback: random_playback(),
-            grid_data: grid_data(),
+            tracks: tracks(),
+            envelope: envelope(),
+            equalizer: equalizer(),
+            humanize: humanize(),
+            transitions: transitions(),
+            mixer: mixer(),
+            grid_columns: Some(grid_columns()),
             created: time,
         };
 
@@ -597,21 +1136,34 @@ pub fn App() -> impl IntoView {
         });
     });
 
+    let generate_handler = Callback::new(move |spec: PatternSpec| {
+        let Some(lib) = sound_lib.get_untracked() else {
+            return;
+        };
+        let envelope = envelope.get_untracked();
+        let Some(generated) = tracks.with_untracked(|t| {
+            t.get(active_track.get_untracked())
+                .map(|track| spec.generate(&lib, track, &envelope, &mut rand::rng()))
+        }) else {
+            return;
+        };
+        set_grid_data(generated);
+        set_selection.update(|s| s.clear());
+    });
+
     // NOTE: Erase grid
     let erase_grid_handler = Callback::new(move |_: ev::MouseEvent| {
-        set_grid_data.update(|grid| {
-            *grid = grid.iter_mut().map(|_| None).collect();
+        update_track(set_tracks, active_track.get_untracked(), |track| {
+            track.grid_data = track.grid_data.iter().map(|_| None).collect();
         });
     });
 
-    let is_cell_filled = Signal::derive(move || {
-        if let Some(idx) = edit_cell_idx() {
-            grid_data().get(idx as usize).unwrap().is_some()
-        } else {
-            false
-        }
+    let edit_cell = Signal::derive(move || {
+        edit_cell_idx().and_then(|idx| grid_data().get(idx as usize).cloned().flatten())
     });
 
+    let is_cell_filled = Signal::derive(move || edit_cell.with(|cell| cell.is_some()));
+
     let is_schedules_empty = Signal::derive(move || {
         if schedule_type() == ScheduleType::Planned {
             planned_schedules().is_empty()
@@ -625,29 +1177,83 @@ pub fn App() -> impl IntoView {
             <SettingsMenu
                 gap_duration
                 set_gap_duration
+                gap_max
+                set_gap_max
+                gap_distribution
+                set_gap_distribution
+                timing
+                set_timing
+                step_interval
+                set_step_interval
+                envelope
+                set_envelope
+                humanize
+                set_humanize
                 grid_size_handler
                 grid_rows_num=Signal::derive(move || {
-                    grid_data.get().len() as u16 / grid_row_size()
+                    grid_data.get().len() as u16 / grid_columns.get()
                 })
                 set_presets_visible
                 set_schedule_visible
+                set_equalizer_visible
+                set_transitions_visible
+                set_generator_visible
                 erase_grid_handler
+                can_undo=Signal::derive(move || history.with(|h| h.can_undo()))
+                can_redo=Signal::derive(move || history.with(|h| h.can_redo()))
+                undo_handler
+                redo_handler
+                loop_select
+                loop_select_handler
+                loop_range
+                clear_loop_handler
             />
 
+            <TrackBar
+                tracks
+                active_track
+                set_active_track
+                add_track_handler
+                remove_track_handler
+                track_volume_handler
+            />
+            <SelectionBar
+                selection
+                is_clipboard_empty=Signal::derive(move || clipboard.with(|c| c.is_empty()))
+                copy_handler
+                paste_handler
+                duplicate_handler
+                clear_handler=clear_selection_handler
+                deselect_handler
+            />
             <Grid
-                grid_data
-                current_cell
+                tracks
+                active_track
+                current_cells
+                columns=grid_columns
                 click_handler=grid_cell_click_handler
                 open_library_handler
-                play
+                playback_state
+                loop_range
+                loop_anchor
+                selection
+                select_handler
+                move_handler
+                row_handler
             />
             <ControlPanel
-                play
-                set_play
+                playback_state
+                play_handler
+                stop_handler
+                skip_handler
                 volume
                 set_volume
-                random_playback
-                set_random_playback
+                track_channel
+                set_track_channel
+                mixer
+                set_mixer
+                playback_order
+                set_playback_order
                 scheduled_playback
                 set_scheduled_playback
                 is_schedules_empty
@@ -666,6 +1272,8 @@ pub fn App() -> impl IntoView {
                                         edit_cell_idx
                                         volume
                                         is_cell_filled
+                                        edit_cell
+                                        cell_change_handler
                                         sample_select_handler
                                         close_library_handler
                                         clear_cell_handler
@@ -675,6 +1283,32 @@ pub fn App() -> impl IntoView {
                     }}
                 </ErrorBoundary>
             </Suspense>
+            <EqualizerPanel
+                equalizer_visible
+                set_equalizer_visible
+                equalizer
+                set_equalizer
+                limiter
+                set_limiter
+            />
+            <TransitionsPanel
+                transitions_visible
+                set_transitions_visible
+                transitions
+                set_transitions
+            />
+            <GeneratorPanel
+                generator_visible
+                set_generator_visible
+                cycle_length=Signal::derive(move || {
+                    tracks
+                        .with(|t| {
+                            t.get(active_track()).map(|track| track.cycle_length(&envelope.get()))
+                        })
+                        .unwrap_or_default()
+                })
+                generate_handler
+            />
             <Presets
                 presets_visible
                 set_presets_visible
@@ -696,27 +1330,14 @@ pub fn App() -> impl IntoView {
                 set_schedule_type
                 presets
             />
-
-            <audio node_ref=main_audio_elem_ref prop:volume=volume on:ended=ended_listener></audio>
-            <audio node_ref=secondary_audio_elem_ref prop:volume=volume></audio>
         </div>
     }
 }
 
-fn fill_grid_initial(grid_data_initial: &mut [Option<Sample>]) {
-    let sample = Sample {
-        id: "boom_hit_1".to_string(),
-        filepath: format!("{SOUND_LIB_PATH}boom/hit_1.mp3"),
-        category: Category::Boom,
-        filename: "hit_1".to_string(),
-        duration: 0.32567,
-    };
-
-    let mod_idx = [0, 2, 4, 6, 8, 10];
-
-    for (idx, item) in grid_data_initial.iter_mut().enumerate() {
-        if mod_idx.contains(&idx) {
-            *item = Some(sample.clone());
+fn update_track(set_tracks: WriteSignal<Vec<Track>>, idx: usize, f: impl FnOnce(&mut Track)) {
+    set_tracks.update(|tracks| {
+        if let Some(track) = tracks.get_mut(idx) {
+            f(track);
         }
-    }
+    });
 }
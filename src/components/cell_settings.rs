@@ -0,0 +1,443 @@
+use crate::shared::{format_filename, Cell, CellPool, Looping, REPEAT_MAX};
+use leptos::{prelude::*, *};
+
+#[component]
+pub fn CellSettings(
+    edit_cell: Signal<Option<Cell>>,
+    #[prop(into)] cell_change_handler: Callback<Cell>,
+) -> impl IntoView {
+    let label_class = "block mb-1 text-xs font-medium text-slate-950 text-left select-none";
+    let input_class = "p-2 border-2 rounded-lg text-sm w-24";
+
+    let update = move |f: &dyn Fn(&mut Cell)| {
+        if let Some(mut cell) = edit_cell.get_untracked() {
+            f(&mut cell);
+            cell_change_handler.run(cell);
+        }
+    };
+
+    let parse_input = |e: &ev::Event| event_target_value(e).trim().parse::<f32>().ok();
+
+    let gain_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.gain = (val / 100.0).clamp(0.0, 2.0));
+        }
+    };
+
+    let pan_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.pan = (val / 100.0).clamp(-1.0, 1.0));
+        }
+    };
+
+    let repeat_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.repeat = (val as u8).clamp(1, REPEAT_MAX));
+        }
+    };
+
+    let rate_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.rate = val.clamp(0.25, 4.0));
+        }
+    };
+
+    let trim_start_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| {
+                let max = (cell.sample.duration - cell.trim_end).max(0.0);
+                cell.trim_start = val.clamp(0.0, max);
+            });
+        }
+    };
+
+    let trim_end_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| {
+                let max = (cell.sample.duration - cell.trim_start).max(0.0);
+                cell.trim_end = val.clamp(0.0, max);
+            });
+        }
+    };
+
+    let probability_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.probability = (val / 100.0).clamp(0.0, 1.0));
+        }
+    };
+
+    let weight_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            update(&|cell| cell.weight = val.clamp(0.0, 10.0));
+        }
+    };
+
+    // NOTE: Empty fields fall back to the track gap and the preset fades
+    let parse_ms = |e: &ev::Event| {
+        event_target_value(e)
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|secs| (secs.max(0.0) * 1000.0) as u64)
+    };
+
+    let gap_handler = move |e: ev::Event| {
+        let gap_override = parse_ms(&e);
+        update(&|cell| cell.gap_override = gap_override);
+    };
+
+    let fade_in_handler = move |e: ev::Event| {
+        let fade_in = parse_ms(&e);
+        update(&|cell| cell.fade_in = fade_in);
+    };
+
+    let excerpt_handler = move |e: ev::Event| {
+        let excerpt = parse_ms(&e).filter(|&ms| ms > 0);
+        update(&|cell| cell.excerpt = excerpt);
+    };
+
+    // NOTE: Switching the unit keeps the amount
+    let looping_handler = move |e: ev::Event| {
+        let kind = event_target_value(&e);
+        update(&|cell| {
+            let amount = cell.looping.map_or(4, |looping| looping.amount());
+            cell.looping = match kind.as_str() {
+                "seconds" => Some(Looping::Seconds(amount)),
+                "times" => Some(Looping::Times(amount)),
+                _ => None,
+            };
+        });
+    };
+
+    let looping_amount_handler = move |e: ev::Event| {
+        if let Some(val) = parse_input(&e) {
+            let amount = (val as u32).max(1);
+            update(&|cell| {
+                cell.looping = cell.looping.map(|looping| match looping {
+                    Looping::Seconds(_) => Looping::Seconds(amount),
+                    Looping::Times(_) => Looping::Times(amount),
+                });
+            });
+        }
+    };
+
+    let fade_out_handler = move |e: ev::Event| {
+        let fade_out = parse_ms(&e);
+        update(&|cell| cell.fade_out = fade_out);
+    };
+
+    let pool_handler = move |e: ev::Event| {
+        if let Some(pool) = event_target_value(&e)
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| CellPool::iter().nth(idx))
+        {
+            update(&|cell| cell.pool = pool);
+        }
+    };
+
+    let value = move |f: fn(&Cell) -> String| {
+        move || edit_cell.get().map(|cell| f(&cell)).unwrap_or_default()
+    };
+
+    view! {
+        <Show when=move || edit_cell.get().is_some()>
+            <div class="flex flex-wrap items-end gap-4 mb-6">
+                <div class="flex flex-col">
+                    <label for="cell-pool" class=label_class>
+                        "Plays"
+                    </label>
+                    <select
+                        id="cell-pool"
+                        class="p-2 border-2 rounded-lg text-sm bg-white"
+                        on:change=pool_handler
+                    >
+                        {CellPool::iter()
+                            .enumerate()
+                            .map(|(idx, pool)| {
+                                view! {
+                                    <option
+                                        value=idx
+                                        prop:selected=move || {
+                                            edit_cell.get().is_some_and(|cell| cell.pool == pool)
+                                        }
+                                    >
+                                        {move || pool_label(pool, edit_cell.get().as_ref())}
+                                    </option>
+                                }
+                            })
+                            .collect_view()}
+                    </select>
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-gain" class=label_class>
+                        {move || {
+                            format!(
+                                "Gain {:.0}%",
+                                edit_cell.get().map_or(100.0, |cell| cell.gain * 100.0),
+                            )
+                        }}
+                    </label>
+                    <input
+                        id="cell-gain"
+                        type="range"
+                        min="0"
+                        max="200"
+                        step="1"
+                        prop:value=value(|cell| (cell.gain * 100.0).to_string())
+                        on:input=gain_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-pan" class=label_class>
+                        {move || {
+                            let pan = edit_cell.get().map_or(0.0, |cell| cell.pan * 100.0);
+                            match pan {
+                                p if p < 0.0 => format!("Pan L{:.0}", -p),
+                                p if p > 0.0 => format!("Pan R{:.0}", p),
+                                _ => "Pan C".to_string(),
+                            }
+                        }}
+                    </label>
+                    <input
+                        id="cell-pan"
+                        type="range"
+                        min="-100"
+                        max="100"
+                        step="1"
+                        prop:value=value(|cell| (cell.pan * 100.0).to_string())
+                        on:input=pan_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-repeat" class=label_class>
+                        "Repeat"
+                    </label>
+                    <input
+                        id="cell-repeat"
+                        class=input_class
+                        type="number"
+                        min="1"
+                        max="8"
+                        step="1"
+                        prop:value=value(|cell| cell.repeat.to_string())
+                        on:change=repeat_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-rate" class=label_class>
+                        "Rate"
+                    </label>
+                    <input
+                        id="cell-rate"
+                        class=input_class
+                        type="number"
+                        min="0.25"
+                        max="4"
+                        step="0.05"
+                        prop:value=value(|cell| cell.rate.to_string())
+                        on:change=rate_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-trim-start" class=label_class>
+                        "Trim start (sec)"
+                    </label>
+                    <input
+                        id="cell-trim-start"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.1"
+                        prop:value=value(|cell| cell.trim_start.to_string())
+                        on:change=trim_start_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-trim-end" class=label_class>
+                        "Trim end (sec)"
+                    </label>
+                    <input
+                        id="cell-trim-end"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.1"
+                        prop:value=value(|cell| cell.trim_end.to_string())
+                        on:change=trim_end_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-excerpt" class=label_class>
+                        {move || {
+                            format!(
+                                "Excerpt of {:.1}s",
+                                edit_cell.get().map_or(0.0, |cell| cell.sample.duration),
+                            )
+                        }}
+                    </label>
+                    <input
+                        id="cell-excerpt"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.5"
+                        placeholder="Whole"
+                        prop:value=value(|cell| format_ms(cell.excerpt))
+                        on:change=excerpt_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-looping" class=label_class>
+                        {move || {
+                            if edit_cell.get().is_some_and(|cell| cell.sample.loop_points.is_some())
+                            {
+                                "Loop"
+                            } else {
+                                "Loop (whole sample)"
+                            }
+                        }}
+                    </label>
+                    <div class="flex gap-2">
+                        <select
+                            id="cell-looping"
+                            class="p-2 border-2 rounded-lg text-sm bg-white"
+                            on:change=looping_handler
+                        >
+                            {[("off", "Off"), ("seconds", "For sec"), ("times", "Times")]
+                                .into_iter()
+                                .map(|(value, label)| {
+                                    view! {
+                                        <option
+                                            value=value
+                                            prop:selected=move || {
+                                                looping_kind(edit_cell.get().as_ref()) == value
+                                            }
+                                        >
+                                            {label}
+                                        </option>
+                                    }
+                                })
+                                .collect_view()}
+                        </select>
+                        <Show when=move || {
+                            edit_cell.get().is_some_and(|cell| cell.looping.is_some())
+                        }>
+                            <input
+                                id="cell-looping-amount"
+                                class="p-2 border-2 rounded-lg text-sm w-16"
+                                type="number"
+                                min="1"
+                                step="1"
+                                prop:value=value(|cell| {
+                                    cell.looping
+                                        .map(|looping| looping.amount().to_string())
+                                        .unwrap_or_default()
+                                })
+                                on:change=looping_amount_handler
+                            />
+                        </Show>
+                    </div>
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-gap" class=label_class>
+                        "Gap after (sec)"
+                    </label>
+                    <input
+                        id="cell-gap"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.5"
+                        placeholder="Track"
+                        prop:value=value(|cell| format_ms(cell.gap_override))
+                        on:change=gap_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-fade-in" class=label_class>
+                        "Fade in (sec)"
+                    </label>
+                    <input
+                        id="cell-fade-in"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.1"
+                        placeholder="Preset"
+                        prop:value=value(|cell| format_ms(cell.fade_in))
+                        on:change=fade_in_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-fade-out" class=label_class>
+                        "Fade out (sec)"
+                    </label>
+                    <input
+                        id="cell-fade-out"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        step="0.1"
+                        placeholder="Preset"
+                        prop:value=value(|cell| format_ms(cell.fade_out))
+                        on:change=fade_out_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-probability" class=label_class>
+                        "Chance %"
+                    </label>
+                    <input
+                        id="cell-probability"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        max="100"
+                        step="5"
+                        prop:value=value(|cell| (cell.probability * 100.0).round().to_string())
+                        on:change=probability_handler
+                    />
+                </div>
+                <div class="flex flex-col">
+                    <label for="cell-weight" class=label_class>
+                        "Weight"
+                    </label>
+                    <input
+                        id="cell-weight"
+                        class=input_class
+                        type="number"
+                        min="0"
+                        max="10"
+                        step="0.5"
+                        prop:value=value(|cell| cell.weight.to_string())
+                        on:change=weight_handler
+                    />
+                </div>
+            </div>
+        </Show>
+    }
+}
+
+fn pool_label(pool: CellPool, cell: Option<&Cell>) -> String {
+    match (pool, cell) {
+        (CellPool::Family, Some(cell)) => {
+            format!("Any {}", format_filename(cell.sample.family_name()))
+        }
+        (CellPool::Category, Some(cell)) => format!("Any {}", cell.sample.category),
+        _ => pool.label().to_string(),
+    }
+}
+
+fn looping_kind(cell: Option<&Cell>) -> &'static str {
+    match cell.and_then(|cell| cell.looping) {
+        Some(Looping::Seconds(_)) => "seconds",
+        Some(Looping::Times(_)) => "times",
+        None => "off",
+    }
+}
+
+fn format_ms(ms: Option<u64>) -> String {
+    ms.map(|ms| (ms as f64 / 1000.0).to_string())
+        .unwrap_or_default()
+}
@@ -1,6 +1,8 @@
+use crate::shared::{Category, Channel, Mixer, PlaybackOrder, PlaybackState};
 use leptos::prelude::*;
 use leptos_heroicons::size_24::outline::{
-    ArrowsRightLeft, Clock, PauseCircle, PlayCircle, SpeakerWave, SpeakerXMark,
+    ArrowsRightLeft, Backward, Clock, Forward, PauseCircle, PlayCircle, Scale, SpeakerWave,
+    SpeakerXMark, StopCircle,
 };
 use leptos_use::{on_click_outside, use_debounce_fn_with_arg};
 use static_str_ops::static_format;
@@ -8,12 +10,18 @@ use web_sys::SvgElement;
 
 #[component]
 pub fn ControlPanel(
-    play: ReadSignal<bool>,
-    set_play: WriteSignal<bool>,
+    playback_state: ReadSignal<PlaybackState>,
+    play_handler: Callback<()>,
+    stop_handler: Callback<()>,
+    skip_handler: Callback<bool>,
     volume: ReadSignal<f32>,
     set_volume: WriteSignal<f32>,
-    random_playback: ReadSignal<bool>,
-    set_random_playback: WriteSignal<bool>,
+    track_channel: Signal<Channel>,
+    set_track_channel: Callback<Channel>,
+    mixer: ReadSignal<Mixer>,
+    set_mixer: WriteSignal<Mixer>,
+    playback_order: Signal<PlaybackOrder>,
+    set_playback_order: Callback<PlaybackOrder>,
     scheduled_playback: ReadSignal<bool>,
     set_scheduled_playback: WriteSignal<bool>,
     is_schedules_empty: Signal<bool>,
@@ -29,18 +37,22 @@ pub fn ControlPanel(
         <div class=outer_container_class>
             <div class=inner_container_class>
                 <div class=left_container_class>
-                    <RandomPlaybackButton random_playback set_random_playback />
+                    <RandomPlaybackButton playback_order set_playback_order />
                 </div>
                 <div class=center_container_class>
+                    <SkipButton forward=false playback_state skip_handler />
                     <PlayButton
-                        play
-                        set_play
+                        playback_state
+                        play_handler
+                        stop_handler
                         scheduled_playback
                         set_scheduled_playback
                         is_schedules_empty
                     />
+                    <SkipButton forward=true playback_state skip_handler />
                 </div>
                 <div class=right_container_class>
+                    <MixerControl track_channel set_track_channel mixer set_mixer />
                     <VolumeControl volume set_volume />
                 </div>
             </div>
@@ -50,8 +62,9 @@ pub fn ControlPanel(
 
 #[component]
 pub fn PlayButton(
-    play: ReadSignal<bool>,
-    set_play: WriteSignal<bool>,
+    playback_state: ReadSignal<PlaybackState>,
+    play_handler: Callback<()>,
+    stop_handler: Callback<()>,
     scheduled_playback: ReadSignal<bool>,
     set_scheduled_playback: WriteSignal<bool>,
     is_schedules_empty: Signal<bool>,
@@ -68,17 +81,17 @@ pub fn PlayButton(
                         if scheduled_playback.get() { "opacity-30" } else { "" },
                     )
                 }
-                on:click=move |_| {
-                    set_play
-                        .update(|val| {
-                            *val = !*val;
-                        })
-                }
+                on:click=move |_| play_handler.run(())
                 disabled=move || scheduled_playback.get()
             >
 
                 <Show
-                    when=move || { play.get() }
+                    when=move || {
+                        matches!(
+                            playback_state.get(),
+                            PlaybackState::Playing | PlaybackState::InGap
+                        )
+                    }
                     fallback=move || {
                         view! { <PlayCircle class=icon_class /> }
                     }
@@ -87,6 +100,16 @@ pub fn PlayButton(
                     <PauseCircle class=icon_class />
                 </Show>
             </button>
+            <Show when=move || playback_state.get() != PlaybackState::Idle>
+                <button
+                    class="flex mr-2"
+                    title="Stop"
+                    on:click=move |_| stop_handler.run(())
+                    disabled=move || scheduled_playback.get()
+                >
+                    <StopCircle class="w-6 h-6 stroke-slate-950" />
+                </button>
+            </Show>
             <button
                 class=move || {
                     format!(
@@ -111,6 +134,31 @@ pub fn PlayButton(
     }
 }
 
+#[component]
+fn SkipButton(
+    forward: bool,
+    playback_state: ReadSignal<PlaybackState>,
+    skip_handler: Callback<bool>,
+) -> impl IntoView {
+    let is_idle = move || playback_state.get() == PlaybackState::Idle;
+    let icon_class = "w-6 h-6 stroke-slate-950";
+
+    view! {
+        <button
+            class=move || format!("flex mx-2 {}", if is_idle() { "opacity-30" } else { "" })
+            title=if forward { "Next" } else { "Previous" }
+            on:click=move |_| skip_handler.run(forward)
+            disabled=is_idle
+        >
+            {if forward {
+                view! { <Forward class=icon_class /> }.into_any()
+            } else {
+                view! { <Backward class=icon_class /> }.into_any()
+            }}
+        </button>
+    }
+}
+
 #[component]
 pub fn VolumeControl(volume: ReadSignal<f32>, set_volume: WriteSignal<f32>) -> impl IntoView {
     let container_class = "relative flex flex-col w-6 h-6";
@@ -173,21 +221,144 @@ pub fn VolumeControl(volume: ReadSignal<f32>, set_volume: WriteSignal<f32>) -> i
     }
 }
 
+#[component]
+pub fn MixerControl(
+    track_channel: Signal<Channel>,
+    set_track_channel: Callback<Channel>,
+    mixer: ReadSignal<Mixer>,
+    set_mixer: WriteSignal<Mixer>,
+) -> impl IntoView {
+    let container_class = "relative flex flex-col w-6 h-6 mr-4";
+    let popover_class =
+        "absolute bottom-10 -right-4 bg-white shadow rounded-2xl p-4 flex flex-col gap-2 text-xs text-slate-950";
+
+    let (open, set_open) = signal(false);
+
+    let popover_ref = NodeRef::new();
+
+    let _ = on_click_outside(popover_ref, move |e| {
+        let target = event_target::<SvgElement>(&e);
+        let tag = target.tag_name();
+        let tags = ["svg", "path"];
+        if open.get() && !tags.contains(&tag.as_str()) {
+            set_open.set(false);
+        }
+    });
+
+    // NOTE: New categories get a strip here without touching the panel
+    let category_rows = Category::iter()
+        .map(|category| {
+            let debounce_fn = use_debounce_fn_with_arg(
+                move |val: f32| {
+                    set_mixer.update(|m| m.update(category, |s| s.volume = val));
+                },
+                300.0,
+            );
+
+            view! {
+                <div class="flex items-center justify-between gap-4">
+                    <span class="w-28">{category.get_emoji()} " " {category.to_string()}</span>
+                    <input
+                        type="range"
+                        min=0
+                        max=100
+                        class="w-24 cursor-pointer"
+                        title=move || {
+                            format!("{:.0}%", mixer.get().strip(category).volume * 100.0)
+                        }
+                        prop:value=move || mixer.get().strip(category).volume * 100.0
+                        on:input=move |e| {
+                            debounce_fn(event_target_value(&e).parse::<f32>().unwrap() / 100.0);
+                        }
+                    />
+                    <ChannelToggle
+                        channel=Signal::derive(move || mixer.get().strip(category).channel)
+                        set_channel=Callback::new(move |channel: Channel| {
+                            set_mixer.update(|m| m.update(category, |s| s.channel = channel));
+                        })
+                    />
+                </div>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class=container_class>
+            <div title="Mixer" on:click=move |_| set_open.update(|val| *val = !*val)>
+                {move || {
+                    let is_routed = track_channel.get() != Channel::Both;
+                    view! {
+                        <Scale class=static_format!(
+                                "cursor-pointer{}",
+                                if is_routed { " stroke-blue-500" } else { " stroke-slate-950" },
+                            )
+                        />
+                    }
+                }}
+            </div>
+            <div
+                node_ref=popover_ref
+                class=move || format!("{popover_class}{}", if open.get() { "" } else { " hidden" })
+            >
+                <div class="flex items-center justify-between gap-4 font-semibold">
+                    <span>"Track"</span>
+                    <ChannelToggle channel=track_channel set_channel=set_track_channel />
+                </div>
+                {category_rows}
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn ChannelToggle(channel: Signal<Channel>, set_channel: Callback<Channel>) -> impl IntoView {
+    Channel::iter()
+        .map(|option| {
+            view! {
+                <button
+                    class=move || {
+                        format!(
+                            "w-9 py-0.5 rounded-full {}",
+                            if channel.get() == option {
+                                "bg-slate-950 text-white"
+                            } else {
+                                "bg-slate-100"
+                            },
+                        )
+                    }
+                    on:click=move |_| set_channel.run(option)
+                >
+                    {option.label()}
+                </button>
+            }
+        })
+        .collect_view()
+}
+
 #[component]
 fn RandomPlaybackButton(
-    random_playback: ReadSignal<bool>,
-    set_random_playback: WriteSignal<bool>,
+    playback_order: Signal<PlaybackOrder>,
+    set_playback_order: Callback<PlaybackOrder>,
 ) -> impl IntoView {
     view! {
         <div
-            class="w-6 h-6 flex mr-4 cursor-pointer"
-            on:click=move |_| { set_random_playback.update(|val| *val = !*val) }
+            class="flex items-center mr-4 cursor-pointer select-none"
+            title=move || playback_order.get().label()
+            on:click=move |_| { set_playback_order.run(playback_order.get_untracked().cycle()) }
         >
-            <ArrowsRightLeft class=static_format!(
-                    "cursor-pointer{}",
-                    if random_playback.get() { " stroke-blue-500" } else { " stroke-slate-950" },
-                )
-            />
+            {move || {
+                let is_random = playback_order.get() != PlaybackOrder::Sequential;
+                view! {
+                    <ArrowsRightLeft class=static_format!(
+                            "w-6 h-6 cursor-pointer{}",
+                            if is_random { " stroke-blue-500" } else { " stroke-slate-950" },
+                        )
+                    />
+                }
+            }}
+            <span class="hidden sm:inline ml-1 text-xs text-slate-950">
+                {move || playback_order.get().label()}
+            </span>
         </div>
     }
 }
@@ -0,0 +1,203 @@
+use crate::components::button::Button;
+use crate::shared::{EqPreset, Equalizer, Limiter};
+use leptos::{prelude::*, *};
+use web_sys::HtmlInputElement;
+
+#[component]
+pub fn EqualizerPanel(
+    equalizer_visible: ReadSignal<bool>,
+    set_equalizer_visible: WriteSignal<bool>,
+    equalizer: ReadSignal<Equalizer>,
+    set_equalizer: WriteSignal<Equalizer>,
+    limiter: ReadSignal<Limiter>,
+    set_limiter: WriteSignal<Limiter>,
+) -> impl IntoView {
+    let container_class =
+        "absolute top-0 right-0 bottom-auto left-0 min-h-screen w-screen bg-white p-2";
+    let preset_button_class = "border-2 rounded-lg py-2 px-4 text-sm select-none";
+
+    let slider = move |label: &'static str,
+                       unit: &'static str,
+                       (min, max, step): (f32, f32, f32),
+                       get: fn(&Equalizer) -> f32,
+                       set: fn(&mut Equalizer, f32)| {
+        view! {
+            <div class="flex flex-col mb-4 w-full max-w-md">
+                <label class="block mb-1 text-xs font-medium text-slate-950 text-left select-none">
+                    {label}
+                    ": "
+                    {move || format!("{:.0} {unit}", equalizer.with(get))}
+                </label>
+                <input
+                    type="range"
+                    min=min
+                    max=max
+                    step=step
+                    prop:value=move || equalizer.with(get)
+                    on:input=move |e| {
+                        if let Ok(val) = event_target_value(&e).parse::<f32>() {
+                            set_equalizer
+                                .update(|eq| {
+                                    set(eq, val);
+                                    eq.preset = EqPreset::Custom;
+                                });
+                        }
+                    }
+                />
+            </div>
+        }
+    };
+
+    view! {
+        <div
+            class=container_class
+            style:display=move || { if equalizer_visible() { "block" } else { "none" } }
+        >
+            <div class="flex flex-wrap gap-2 mb-6">
+                {EqPreset::iter()
+                    .map(|preset| {
+                        view! {
+                            <button
+                                class=move || {
+                                    format!(
+                                        "{preset_button_class} {}",
+                                        if equalizer.get().preset == preset {
+                                            "border-slate-950"
+                                        } else {
+                                            "border-slate-200 hover:border-slate-600"
+                                        },
+                                    )
+                                }
+                                on:click=move |_| set_equalizer(Equalizer::from_preset(preset))
+                            >
+                                {preset.label()}
+                            </button>
+                        }
+                    })
+                    .collect_view()}
+            </div>
+
+            {slider(
+                "Low shelf frequency",
+                "Hz",
+                (30.0, 300.0, 5.0),
+                |eq| eq.low_shelf_freq,
+                |eq, v| eq.low_shelf_freq = v,
+            )}
+            {slider(
+                "Low shelf gain",
+                "dB",
+                (-12.0, 18.0, 1.0),
+                |eq| eq.low_shelf_gain,
+                |eq, v| eq.low_shelf_gain = v,
+            )}
+            {slider(
+                "Mid frequency",
+                "Hz",
+                (100.0, 2000.0, 10.0),
+                |eq| eq.mid_freq,
+                |eq, v| eq.mid_freq = v,
+            )}
+            {slider("Mid gain", "dB", (-12.0, 12.0, 1.0), |eq| eq.mid_gain, |eq, v| eq.mid_gain = v)}
+            {slider(
+                "High cut",
+                "Hz",
+                (500.0, 20000.0, 100.0),
+                |eq| eq.high_cut_freq,
+                |eq, v| eq.high_cut_freq = v,
+            )}
+
+            <LimiterControl limiter set_limiter />
+
+            <div class="fixed bottom-[4%] w-screen h-[56px] left-0">
+                <div class="w-60 h-[100%] mx-auto flex items-center justify-center">
+                    <Button on:click=move |_| {
+                        set_equalizer_visible.update(|val| { *val = !*val })
+                    }>Close</Button>
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn LimiterControl(
+    limiter: ReadSignal<Limiter>,
+    set_limiter: WriteSignal<Limiter>,
+) -> impl IntoView {
+    let label_class = "block mb-1 text-xs font-medium text-slate-950 text-left select-none";
+
+    view! {
+        <div class="flex flex-col w-full max-w-md pt-4 mt-2 mb-24 border-t-[1px] border-slate-200">
+            <label class="flex items-center mb-4 text-sm font-medium text-slate-950 select-none">
+                <input
+                    type="checkbox"
+                    class="mr-2"
+                    prop:checked=move || limiter.get().enabled
+                    on:change=move |e| {
+                        let enabled = event_target_checked(&e);
+                        set_limiter.update(|l| l.enabled = enabled);
+                    }
+                />
+                "Speaker protection"
+            </label>
+            <div class="flex flex-col mb-4">
+                <label class=label_class>
+                    {move || format!("Ceiling: {:.0} dB", limiter.get().ceiling)}
+                </label>
+                <input
+                    type="range"
+                    min="-24"
+                    max="0"
+                    step="1"
+                    prop:value=move || limiter.get().ceiling
+                    disabled=move || !limiter.get().enabled
+                    on:input=move |e| {
+                        if let Ok(val) = event_target_value(&e).parse::<f32>() {
+                            set_limiter.update(|l| l.ceiling = val);
+                        }
+                    }
+                />
+            </div>
+            <div class="flex flex-col">
+                <label class=label_class>
+                    {move || format!("Max volume: {:.0}%", limiter.get().max_volume * 100.0)}
+                </label>
+                <input
+                    type="range"
+                    min="0"
+                    max="100"
+                    step="1"
+                    prop:value=move || limiter.get().max_volume * 100.0
+                    disabled=move || limiter.get().locked
+                    on:input=move |e| {
+                        if let Ok(val) = event_target_value(&e).parse::<f32>() {
+                            set_limiter.update(|l| l.max_volume = val / 100.0);
+                        }
+                    }
+                />
+            </div>
+            <label class="flex items-center mt-4 text-sm font-medium text-slate-950 select-none">
+                <input
+                    type="checkbox"
+                    class="mr-2"
+                    prop:checked=move || limiter.get().locked
+                    on:change=move |e| {
+                        let locked = event_target_checked(&e);
+                        // NOTE: Unlocking asks first, a stray tap must not lift the cap
+                        let confirmed = locked
+                            || window()
+                                .confirm_with_message("Unlock the max volume?")
+                                .unwrap_or(false);
+                        if confirmed {
+                            set_limiter.update(|l| l.locked = locked);
+                        } else {
+                            event_target::<HtmlInputElement>(&e).set_checked(true);
+                        }
+                    }
+                />
+                "Lock max volume"
+            </label>
+        </div>
+    }
+}
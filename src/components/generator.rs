@@ -0,0 +1,144 @@
+use crate::components::button::Button;
+use crate::shared::{format_filename, Category, PatternSpec};
+use leptos::prelude::*;
+
+#[component]
+pub fn GeneratorPanel(
+    generator_visible: ReadSignal<bool>,
+    set_generator_visible: WriteSignal<bool>,
+    cycle_length: Signal<f32>,
+    #[prop(into)] generate_handler: Callback<PatternSpec>,
+) -> impl IntoView {
+    let container_class =
+        "absolute top-0 right-0 bottom-auto left-0 min-h-screen w-screen bg-white p-2";
+    let category_button_class = "border-2 rounded-lg py-2 px-4 text-sm select-none";
+    let label_class = "block mb-1 text-xs font-medium text-slate-950 text-left select-none";
+
+    let (spec, set_spec) = signal(PatternSpec::default());
+
+    let slider = move |label: &'static str,
+                       (min, max, step): (f32, f32, f32),
+                       format: fn(f32) -> String,
+                       get: fn(&PatternSpec) -> f32,
+                       set: fn(&mut PatternSpec, f32)| {
+        view! {
+            <div class="flex flex-col mb-4 w-full max-w-md">
+                <label class=label_class>{label} ": " {move || format(spec.with(get))}</label>
+                <input
+                    type="range"
+                    min=min
+                    max=max
+                    step=step
+                    prop:value=move || spec.with(get)
+                    on:input=move |e| {
+                        if let Ok(val) = event_target_value(&e).parse::<f32>() {
+                            set_spec.update(|s| set(s, val));
+                        }
+                    }
+                />
+            </div>
+        }
+    };
+
+    let toggle_category = move |category: Category| {
+        set_spec.update(|s| {
+            if let Some(pos) = s.categories.iter().position(|c| *c == category) {
+                s.categories.remove(pos);
+            } else {
+                s.categories.push(category);
+            }
+        });
+    };
+
+    view! {
+        <div
+            class=container_class
+            style:display=move || { if generator_visible() { "block" } else { "none" } }
+        >
+            <p class="mb-4 text-sm text-slate-950 select-none">
+                "Fill the grid with random sounds of the chosen categories"
+            </p>
+            <div class="flex flex-wrap gap-2 mb-6">
+                {Category::iter()
+                    .map(|category| {
+                        view! {
+                            <button
+                                class=move || {
+                                    format!(
+                                        "{category_button_class} {}",
+                                        if spec.with(|s| s.categories.contains(&category)) {
+                                            "border-slate-950"
+                                        } else {
+                                            "border-slate-200 hover:border-slate-600"
+                                        },
+                                    )
+                                }
+                                on:click=move |_| toggle_category(category)
+                            >
+                                {category.get_emoji()}
+                                " "
+                                {format_filename(&category.to_string())}
+                            </button>
+                        }
+                    })
+                    .collect_view()}
+            </div>
+
+            {slider(
+                "Empty cells",
+                (0.0, 0.9, 0.05),
+                |v| format!("{:.0}%", v * 100.0),
+                |s| s.density,
+                |s, v| s.density = v,
+            )}
+            {slider(
+                "Cycle length",
+                (5.0, 600.0, 5.0),
+                |v| format!("{v:.0} sec"),
+                |s| s.cycle_seconds,
+                |s, v| s.cycle_seconds = v,
+            )}
+            {slider(
+                "Max sample duration",
+                (1.0, 60.0, 1.0),
+                |v| format!("{v:.0} sec"),
+                |s| s.max_duration,
+                |s, v| s.max_duration = v,
+            )}
+
+            <div class="flex items-center gap-2 mb-4">
+                <input
+                    type="checkbox"
+                    id="generator-merge"
+                    prop:checked=move || spec.with(|s| s.merge)
+                    on:change=move |e| {
+                        set_spec.update(|s| s.merge = event_target_checked(&e));
+                    }
+                />
+                <label for="generator-merge" class="text-sm text-slate-950 select-none">
+                    "Keep the filled cells"
+                </label>
+            </div>
+            <p class="mb-6 text-xs text-slate-500 select-none">
+                {move || format!("Current cycle: {:.0} sec", cycle_length())}
+            </p>
+
+            <div class="fixed bottom-[4%] w-screen h-[56px] left-0">
+                <div class="w-60 h-[100%] mx-auto flex items-center justify-center gap-2">
+                    <Button
+                        disabled=Signal::derive(move || spec.with(|s| s.categories.is_empty()))
+                        on:click=move |_| {
+                            generate_handler.run(spec.get_untracked());
+                            set_generator_visible(false);
+                        }
+                    >
+                        Generate
+                    </Button>
+                    <Button on:click=move |_| {
+                        set_generator_visible.update(|val| { *val = !*val })
+                    }>Close</Button>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -1,22 +1,76 @@
-use crate::shared::{format_filename, Sample};
+use crate::shared::{
+    format_filename, CellPool, PlaybackState, RowOp, SelectMode, Selection, Track,
+};
 use leptos::ev;
 use leptos::prelude::*;
-use leptos_use::{use_timeout_fn, UseTimeoutFnReturn};
-use web_sys::HtmlDivElement;
+use leptos_heroicons::size_24::outline::EllipsisVertical;
+use leptos_use::{on_click_outside, use_timeout_fn, UseTimeoutFnReturn};
+use web_sys::{Element, HtmlDivElement, SvgElement};
+
+// NOTE: A touch held this long selects the cell and arms moving the selection
+const LONG_PRESS_MS: f64 = 500.0;
+// NOTE: Pixels a held touch may drift before it counts as a scroll instead
+const LONG_PRESS_SLOP: i32 = 10;
 
 #[component]
 pub fn Grid(
-    grid_data: ReadSignal<Vec<Option<Sample>>>,
-    current_cell: ReadSignal<usize>,
+    tracks: ReadSignal<Vec<Track>>,
+    active_track: ReadSignal<usize>,
+    current_cells: ReadSignal<Vec<usize>>,
+    columns: ReadSignal<u16>,
     click_handler: Callback<(Option<String>, u16)>,
     #[prop(into)] open_library_handler: Callback<u16>,
-    play: ReadSignal<bool>,
+    playback_state: ReadSignal<PlaybackState>,
+    loop_range: Signal<Option<(usize, usize)>>,
+    loop_anchor: ReadSignal<Option<u16>>,
+    selection: ReadSignal<Selection>,
+    select_handler: Callback<(u16, SelectMode)>,
+    move_handler: Callback<(u16, u16)>,
+    row_handler: Callback<(usize, RowOp)>,
 ) -> impl IntoView {
-    let container_class = "px-6 pb-20 grid grid-cols-4 sm:grid-cols-6 gap-2";
+    let container_class = "px-6 pb-20 grid gap-2 min-w-fit";
     let item_class = "relative h-16 rounded shadow-sm flex justify-center items-center hover:cursor-pointer hover:border-2 hover:shadow-lg active:shadow-sm bg-white/80 backdrop-blur-md";
     let item_active_class = "border-2 border-amber-400 rounded-lg";
+    let item_loop_class = "ring-2 ring-sky-300";
+    let item_anchor_class = "ring-2 ring-sky-500";
+    let item_selected_class = "ring-2 ring-violet-500";
+    let item_drop_class = "ring-4 ring-violet-300";
     let content_class = "flex flex-col items-center pointer-events-none select-none text-xs";
 
+    let grid_data = Memo::new(move |_| {
+        tracks.with(|t| {
+            t.get(active_track())
+                .map(|track| track.grid_data.clone())
+                .unwrap_or_default()
+        })
+    });
+    let rows = Memo::new(move |_| grid_data.with(|gd| gd.len()) / columns() as usize);
+
+    // NOTE: Cell picked by a long press, the next tap moves the selection there
+    let (move_from, set_move_from) = signal::<Option<u16>>(None);
+    let (drag_from, set_drag_from) = signal::<Option<u16>>(None);
+    let (drop_target, set_drop_target) = signal::<Option<u16>>(None);
+    let press_origin = StoredValue::new((0, 0));
+    let is_touch = StoredValue::new(false);
+    let suppress_click = StoredValue::new(false);
+
+    let ring_class = move |idx: usize| {
+        if drop_target() == Some(idx as u16) {
+            item_drop_class
+        } else if loop_anchor() == Some(idx as u16) {
+            item_anchor_class
+        } else if selection.with(|s| s.contains(idx)) {
+            item_selected_class
+        } else if loop_range().is_some_and(|(start, end)| (start..=end).contains(&idx)) {
+            item_loop_class
+        } else {
+            ""
+        }
+    };
+    let current_cell = Memo::new(move |_| {
+        current_cells.with(|cells| cells.get(active_track()).copied().unwrap_or(0))
+    });
+
     let UseTimeoutFnReturn {
         start,
         stop,
@@ -44,12 +98,108 @@ pub fn Grid(
         )
     };
 
+    let UseTimeoutFnReturn {
+        start: start_long_press,
+        stop: stop_long_press,
+        ..
+    } = use_timeout_fn(
+        move |idx: u16| {
+            suppress_click.set_value(true);
+            select_handler.run((idx, SelectMode::Toggle));
+            set_move_from(Some(idx));
+        },
+        LONG_PRESS_MS,
+    );
+
     let local_click_handler = move |e: ev::MouseEvent| {
+        if suppress_click.get_value() {
+            suppress_click.set_value(false);
+            return;
+        }
+        let Some(idx) = cell_idx(&e) else {
+            return;
+        };
+
+        if let Some(from) = move_from.get_untracked() {
+            set_move_from(None);
+            if from != idx {
+                move_handler.run((from, idx));
+            }
+            return;
+        }
+        if e.shift_key() {
+            select_handler.run((idx, SelectMode::Extend));
+            return;
+        }
+        if e.ctrl_key() || e.meta_key() {
+            select_handler.run((idx, SelectMode::Toggle));
+            return;
+        }
+
         if !is_pending() {
             start(e);
         }
     };
 
+    let pointer_down_handler = move |e: ev::PointerEvent| {
+        is_touch.set_value(e.pointer_type() == "touch");
+        suppress_click.set_value(false);
+        if !is_touch.get_value() {
+            return;
+        }
+        if let Some(idx) = cell_idx(&e) {
+            press_origin.set_value((e.client_x(), e.client_y()));
+            start_long_press(idx);
+        }
+    };
+
+    let pointer_move_handler = move |e: ev::PointerEvent| {
+        let (x, y) = press_origin.get_value();
+        if (e.client_x() - x).abs() > LONG_PRESS_SLOP || (e.client_y() - y).abs() > LONG_PRESS_SLOP
+        {
+            stop_long_press();
+        }
+    };
+
+    let drag_start_handler = move |e: ev::DragEvent| {
+        let Some(idx) = cell_idx(&e) else {
+            return;
+        };
+        set_drag_from(Some(idx));
+        // NOTE: Firefox only starts a drag that carries data
+        if let Some(data_transfer) = e.data_transfer() {
+            data_transfer.set_effect_allowed("move");
+            let _ = data_transfer.set_data("text/plain", &idx.to_string());
+        }
+    };
+
+    let drag_over_handler = move |e: ev::DragEvent| {
+        if drag_from.get_untracked().is_none() {
+            return;
+        }
+        e.prevent_default();
+        let idx = cell_idx(&e);
+        if drop_target.get_untracked() != idx {
+            set_drop_target(idx);
+        }
+    };
+
+    let drop_handler = move |e: ev::DragEvent| {
+        e.prevent_default();
+        if let (Some(from), Some(to)) = (drag_from.get_untracked(), cell_idx(&e)) {
+            if from != to {
+                move_handler.run((from, to));
+            }
+        }
+        set_drag_from(None);
+        set_drop_target(None);
+    };
+
+    let drag_end_handler = move |_: ev::DragEvent| {
+        set_drag_from(None);
+        set_drop_target(None);
+    };
+
     let double_click_handler = move |e: ev::MouseEvent| {
         if is_pending() {
             stop();
@@ -61,73 +211,197 @@ pub fn Grid(
     };
 
     let grid_elems = move || {
+        let row_size = columns() as usize;
         grid_data()
             .into_iter()
             .enumerate()
             .map(|(idx, elem)| {
+                let is_filled = elem.is_some();
+                // NOTE: Each row ends in its menu, placed in the extra grid column
+                let row_menu = ((idx + 1) % row_size == 0)
+                    .then(|| {
+                        view! { <RowMenu row=idx / row_size rows=rows.into() row_handler /> }
+                    });
                 view! {
                     <div
-                        data-sound-url=if let Some(sample) = elem.clone() {
-                            sample.filepath
+                        data-sound-url=if let Some(cell) = elem.clone() {
+                            cell.sample.filepath
                         } else {
                             "".to_string()
                         }
 
                         data-idx=idx
+                        draggable=move || {
+                            if is_filled || selection.with(|s| s.contains(idx)) {
+                                "true"
+                            } else {
+                                "false"
+                            }
+                        }
                         class=move || {
                             format!(
-                                "{item_class} {} {}",
+                                "{item_class} {} {} {} {}",
                                 if idx == current_cell() { item_active_class } else { "" },
                                 if idx != current_cell() { "hover:border-white" } else { "" },
+                                ring_class(idx),
+                                if move_from() == Some(idx as u16) { "animate-pulse" } else { "" },
                             )
                         }
                     >
-                        {if let Some(sample) = elem.clone() {
-                            let icon = sample.category.get_emoji();
-                            let filename = format_filename(&sample.filename);
-                            let duration = format!("{:.2}s", &sample.duration);
+                        {if let Some(cell) = elem.clone() {
+                            let icon = cell.sample.category.get_emoji();
+                            // NOTE: Pool cells show what they draw from, not the sample picked
+                            let filename = match cell.pool {
+                                CellPool::Sample => format_filename(&cell.sample.filename),
+                                CellPool::Family => {
+                                    format!("{} *", format_filename(cell.sample.family_name()))
+                                }
+                                CellPool::Category => {
+                                    format!("{} *", format_filename(&cell.sample.category.to_string()))
+                                }
+                            };
+                            let play_duration = cell.play_duration();
+                            let duration = if cell.repeat > 1 {
+                                format!("{:.2}s ×{}", play_duration, cell.repeat)
+                            } else {
+                                format!("{:.2}s", play_duration)
+                            };
                             view! {
+                                {(cell.probability < 1.0)
+                                    .then(|| {
+                                        view! {
+                                            <div class="absolute top-1 right-1 text-[10px] text-slate-500 pointer-events-none select-none">
+                                                {format!("{:.0}%", cell.probability * 100.0)}
+                                            </div>
+                                        }
+                                    })}
                                 <div class=content_class>
                                     <div>{icon}</div>
                                     <div class="font-semibold">{filename}</div>
                                     <div>{duration}</div>
                                 </div>
-                                <div
-                                    class=move || {
-                                        format!(
-                                            "absolute top-0 right-0 bottom-0 left-0 w-0 bg-amber-600 h-[100%] ease-linear opacity-10{}",
-                                            if idx == current_cell() && play() {
-                                                " w-[100%] transition-all"
-                                            } else {
-                                                " opacity-0"
-                                            },
+                                // NOTE: An animation rather than a transition, so pausing can hold it
+                                <Show when=move || {
+                                    idx == current_cell() && playback_state() != PlaybackState::Idle
+                                }>
+                                    <div
+                                        class="cell-progress absolute top-0 right-0 bottom-0 left-0 w-0 bg-amber-600 h-[100%] opacity-10"
+                                        style:animation-duration=format!(
+                                            "{:.0}ms",
+                                            play_duration * 1000.0,
                                         )
-                                    }
-                                    style=move || {
-                                        if idx == current_cell() && play() {
-                                            format!(
-                                                "transition-duration: {:.0}ms",
-                                                sample.duration * 1000.0,
-                                            )
-                                        } else {
-                                            "transition-duration: 0".to_string()
+                                        style:animation-play-state=move || {
+                                            if playback_state() == PlaybackState::Paused {
+                                                "paused"
+                                            } else {
+                                                "running"
+                                            }
                                         }
-                                    }
-                                ></div>
+                                    ></div>
+                                </Show>
                             }
                                 .into_any()
                         } else {
                             view! { "" }.into_any()
                         }}
                     </div>
+                    {row_menu}
                 }
             })
             .collect_view()
     };
 
     view! {
-        <div class=container_class on:click=local_click_handler on:dblclick=double_click_handler>
+        <div
+            class=container_class
+            // NOTE: Cells keep a usable width, a narrow screen scrolls a wide grid instead
+            style:grid-template-columns=move || {
+                format!("repeat({}, minmax(3.5rem, 1fr)) auto", columns())
+            }
+            on:click=local_click_handler
+            on:dblclick=double_click_handler
+            on:pointerdown=pointer_down_handler
+            on:pointermove=pointer_move_handler
+            on:pointerup=move |_| stop_long_press()
+            on:pointercancel=move |_| stop_long_press()
+            on:contextmenu=move |e| {
+                if is_touch.get_value() {
+                    e.prevent_default();
+                }
+            }
+            on:dragstart=drag_start_handler
+            on:dragover=drag_over_handler
+            on:drop=drop_handler
+            on:dragend=drag_end_handler
+        >
             {grid_elems}
         </div>
     }
 }
+
+#[component]
+fn RowMenu(
+    row: usize,
+    rows: Signal<usize>,
+    row_handler: Callback<(usize, RowOp)>,
+) -> impl IntoView {
+    let popover_class =
+        "absolute top-8 right-0 z-10 bg-white shadow rounded-2xl py-2 flex flex-col text-xs text-slate-950 whitespace-nowrap";
+    let option_class =
+        "px-4 py-1 text-left hover:bg-slate-100 disabled:opacity-30 disabled:hover:bg-transparent";
+
+    let (open, set_open) = signal(false);
+
+    let popover_ref = NodeRef::new();
+
+    let _ = on_click_outside(popover_ref, move |e| {
+        let target = event_target::<SvgElement>(&e);
+        let tag = target.tag_name();
+        let tags = ["svg", "path"];
+        if open.get() && !tags.contains(&tag.as_str()) {
+            set_open.set(false);
+        }
+    });
+
+    let options = RowOp::iter()
+        .map(|op| {
+            view! {
+                <button
+                    class=option_class
+                    disabled=move || !op.is_allowed(row, rows.get())
+                    on:click=move |_| {
+                        set_open.set(false);
+                        row_handler.run((row, op));
+                    }
+                >
+                    {op.label()}
+                </button>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class="relative flex items-center">
+            <div title="Row" on:click=move |_| set_open.update(|val| *val = !*val)>
+                <EllipsisVertical class="w-5 h-5 cursor-pointer stroke-white" />
+            </div>
+            <div
+                node_ref=popover_ref
+                class=move || format!("{popover_class}{}", if open.get() { "" } else { " hidden" })
+            >
+                {options}
+            </div>
+        </div>
+    }
+}
+
+// NOTE: The cell an event happened in, also when it hit an overlay inside the cell
+fn cell_idx(e: &ev::Event) -> Option<u16> {
+    event_target::<Element>(e)
+        .closest("[data-idx]")
+        .ok()
+        .flatten()?
+        .get_attribute("data-idx")?
+        .parse()
+        .ok()
+}
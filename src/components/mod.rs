@@ -1,7 +1,13 @@
 pub mod button;
+pub mod cell_settings;
 pub mod control_panel;
+pub mod equalizer;
+pub mod generator;
 pub mod grid;
 pub mod presets;
 pub mod schedule;
+pub mod selection_bar;
 pub mod settings_menu;
 pub mod sound_library;
+pub mod tracks;
+pub mod transitions;
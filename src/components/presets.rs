@@ -3,7 +3,7 @@ use std::collections::HashSet;
 use crate::html::Input;
 use crate::{
     components::button::Button,
-    shared::{Preset, Sample},
+    shared::{Cell, PlaybackOrder, Preset, TimingMode},
 };
 use chrono::Utc;
 use leptos::{prelude::*, *};
@@ -47,16 +47,41 @@ pub fn Presets(
                     each=move || presets.get()
                     key=move |preset| preset.id.clone()
                     children=move |preset: Preset| {
-                        let total_cells_num = preset.grid_data.len();
-                        let filled_cells: Vec<&Option<Sample>> = preset
-                            .grid_data
+                        let total_cells_num: usize = preset
+                            .tracks
                             .iter()
+                            .map(|track| track.grid_data.len())
+                            .sum();
+                        let filled_cells: Vec<&Option<Cell>> = preset
+                            .tracks
+                            .iter()
+                            .flat_map(|track| track.grid_data.iter())
                             .filter(|cell| cell.is_some())
                             .collect();
                         let filled_cells_num = filled_cells.len();
+                        let tracks_num = preset.tracks.len();
+                        let first_track = preset.tracks.first();
+                        let (gap_min, gap_max) = first_track.map_or((0, 0), |track| track.gap_range());
+                        let gap_label = if let Some(track) = first_track
+                            .filter(|track| track.timing == TimingMode::Step)
+                        {
+                            format!("every {:.2}s", track.step_interval as f64 / 1000.0)
+                        } else if gap_max > gap_min {
+                            format!(
+                                "{:.2}-{:.2}s",
+                                gap_min as f64 / 1000.0,
+                                gap_max as f64 / 1000.0,
+                            )
+                        } else {
+                            format!("{:.2}s", gap_min as f64 / 1000.0)
+                        };
+                        let random_playback = preset
+                            .tracks
+                            .iter()
+                            .any(|track| track.playback_order != PlaybackOrder::Sequential);
                         let category_emojis_hs = filled_cells
                             .iter()
-                            .map(|cell| { cell.as_ref().unwrap().category.get_emoji() })
+                            .map(|cell| { cell.as_ref().unwrap().sample.category.get_emoji() })
                             .collect::<HashSet<char>>();
                         let mut category_emojis_vec = category_emojis_hs
                             .iter()
@@ -78,12 +103,20 @@ pub fn Presets(
                                     <div class="text-xs select-none">
                                         <span>{filled_cells_num}/{total_cells_num}</span>
                                         " | "
-                                        <span>{format!("{:.2}s", preset.gap_duration / 1000)}</span>
+                                        <span>{gap_label}</span>
+                                        {if tracks_num > 1 { " | " } else { "" }}
+                                        <span>
+                                            {if tracks_num > 1 {
+                                                format!("{tracks_num} tracks")
+                                            } else {
+                                                String::new()
+                                            }}
+                                        </span>
                                         " | "
                                         <span>{category_emojis}</span>
-                                        {if preset.random_playback { " | " } else { "" }}
+                                        {if random_playback { " | " } else { "" }}
                                         <span>
-                                            {if preset.random_playback {
+                                            {if random_playback {
                                                 view! { <ArrowsRightLeft class="inline w-4 h-4" /> }
                                                     .into_any()
                                             } else {
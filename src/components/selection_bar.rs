@@ -0,0 +1,54 @@
+use crate::shared::Selection;
+use leptos::prelude::*;
+
+#[component]
+pub fn SelectionBar(
+    selection: ReadSignal<Selection>,
+    is_clipboard_empty: Signal<bool>,
+    copy_handler: Callback<()>,
+    paste_handler: Callback<()>,
+    duplicate_handler: Callback<()>,
+    clear_handler: Callback<()>,
+    deselect_handler: Callback<()>,
+) -> impl IntoView {
+    let container_class = "px-6 pb-2 flex flex-wrap items-center gap-2 text-xs select-none";
+    let button_class = "h-8 px-3 rounded-full font-semibold shadow-sm bg-white/80 backdrop-blur-md text-slate-950 hover:bg-white disabled:opacity-30";
+
+    view! {
+        <Show when=move || selection.with(|s| !s.is_empty())>
+            <div class=container_class>
+                <span class="text-white font-semibold mr-2">
+                    {move || format!("{} selected", selection.with(|s| s.cells().len()))}
+                </span>
+                <button class=button_class title="Ctrl+C" on:click=move |_| copy_handler.run(())>
+                    "Copy"
+                </button>
+                <button
+                    class=button_class
+                    title="Ctrl+V"
+                    disabled=is_clipboard_empty
+                    on:click=move |_| paste_handler.run(())
+                >
+                    "Paste"
+                </button>
+                <button
+                    class=button_class
+                    title="Ctrl+D"
+                    on:click=move |_| duplicate_handler.run(())
+                >
+                    "Duplicate"
+                </button>
+                <button
+                    class=format!("{button_class} hover:text-red-500")
+                    title="Delete"
+                    on:click=move |_| clear_handler.run(())
+                >
+                    "Clear"
+                </button>
+                <button class=button_class title="Esc" on:click=move |_| deselect_handler.run(())>
+                    "Done"
+                </button>
+            </div>
+        </Show>
+    }
+}
@@ -1,18 +1,48 @@
-use crate::shared::{Operation, GRID_ROWS_MAX, GRID_ROWS_MIN};
+use crate::shared::{
+    format_filename, Envelope, GapDistribution, Humanize, Operation, TimingMode, GRID_ROWS_MAX,
+    GRID_ROWS_MIN,
+};
 use leptos::{prelude::*, *};
-use leptos_heroicons::size_24::outline::{Bars2, CalendarDays, Folder, XMark};
+use leptos_heroicons::size_24::outline::{
+    AdjustmentsHorizontal, ArrowUturnLeft, ArrowUturnRight, ArrowsRightLeft, Bars2, CalendarDays,
+    Folder, Sparkles, XMark,
+};
 use leptos_use::on_click_outside;
+use std::str::FromStr;
 use web_sys::HtmlInputElement;
 
 #[component]
 pub fn SettingsMenu(
-    gap_duration: ReadSignal<u64>,
-    set_gap_duration: WriteSignal<u64>,
+    gap_duration: Signal<u64>,
+    #[prop(into)] set_gap_duration: Callback<u64>,
+    gap_max: Signal<u64>,
+    #[prop(into)] set_gap_max: Callback<u64>,
+    gap_distribution: Signal<GapDistribution>,
+    #[prop(into)] set_gap_distribution: Callback<GapDistribution>,
+    timing: Signal<TimingMode>,
+    #[prop(into)] set_timing: Callback<TimingMode>,
+    step_interval: Signal<u64>,
+    #[prop(into)] set_step_interval: Callback<u64>,
+    envelope: ReadSignal<Envelope>,
+    set_envelope: WriteSignal<Envelope>,
+    humanize: ReadSignal<Humanize>,
+    set_humanize: WriteSignal<Humanize>,
     grid_rows_num: Signal<u16>,
     #[prop(into)] grid_size_handler: Callback<Operation>,
     set_presets_visible: WriteSignal<bool>,
     set_schedule_visible: WriteSignal<bool>,
+    set_equalizer_visible: WriteSignal<bool>,
+    set_transitions_visible: WriteSignal<bool>,
+    set_generator_visible: WriteSignal<bool>,
     #[prop(into)] erase_grid_handler: Callback<ev::MouseEvent>,
+    can_undo: Signal<bool>,
+    can_redo: Signal<bool>,
+    undo_handler: Callback<()>,
+    redo_handler: Callback<()>,
+    loop_select: ReadSignal<bool>,
+    loop_select_handler: Callback<()>,
+    loop_range: Signal<Option<(usize, usize)>>,
+    clear_loop_handler: Callback<()>,
 ) -> impl IntoView {
     let (open, set_open) = signal(false);
 
@@ -20,10 +50,11 @@ pub fn SettingsMenu(
     let _ = on_click_outside(menu_ref, move |_| set_open.set(false));
 
     let menu_base_class =
-        "absolute -bottom-[270px] right-4 w-40 h-content rounded-lg bg-white z-10 flex flex-col items-start gap-2 px-4 py-2 cursor-default shadow";
+        "absolute top-full right-4 w-40 h-content rounded-lg bg-white z-10 flex flex-col items-start gap-2 px-4 py-2 cursor-default shadow";
 
     view! {
         <div class="relative flex justify-end">
+            <HistoryButtons can_undo can_redo undo_handler redo_handler />
             <button
                 class="p-2 cursor-pointer"
                 on:click=move |_| set_open.update(|val| { *val = !*val })
@@ -44,12 +75,51 @@ pub fn SettingsMenu(
                 }
                 node_ref=menu_ref
             >
-                <PlaybackGapDuration gap_duration set_gap_duration />
+                <TimingModeSelect timing set_timing />
+                <Show
+                    when=move || timing.get() == TimingMode::Step
+                    fallback=move || {
+                        view! {
+                            <PlaybackGapDuration
+                                label="Min gap (sec)"
+                                input_id="gap-min-input"
+                                gap_duration
+                                set_gap_duration
+                            />
+                            <PlaybackGapDuration
+                                label="Max gap (sec)"
+                                input_id="gap-max-input"
+                                gap_duration=gap_max
+                                set_gap_duration=set_gap_max
+                            />
+                            <GapDistributionSelect gap_distribution set_gap_distribution />
+                        }
+                    }
+                >
+                    <PlaybackGapDuration
+                        label="Step (sec)"
+                        input_id="step-input"
+                        gap_duration=step_interval
+                        set_gap_duration=set_step_interval
+                    />
+                </Show>
+                <EnvelopeControl envelope set_envelope />
+                <HumanizeControl humanize set_humanize />
                 <GridSizeControl grid_rows_num grid_size_handler />
+                <LoopRangeControl
+                    loop_select
+                    loop_select_handler
+                    loop_range
+                    clear_loop_handler
+                    set_open
+                />
                 <EraseGridButton erase_grid_handler set_open />
                 <div class="border-b-[1px] border-slate-200 w-full -mb-2"></div>
                 <PresetsButton set_presets_visible set_open />
                 <ScheduleButton set_schedule_visible set_open />
+                <EqualizerButton set_equalizer_visible set_open />
+                <TransitionsButton set_transitions_visible set_open />
+                <GeneratorButton set_generator_visible set_open />
             </div>
         </div>
     }
@@ -57,8 +127,10 @@ pub fn SettingsMenu(
 
 #[component]
 pub fn PlaybackGapDuration(
-    gap_duration: ReadSignal<u64>,
-    set_gap_duration: WriteSignal<u64>,
+    label: &'static str,
+    input_id: &'static str,
+    gap_duration: Signal<u64>,
+    #[prop(into)] set_gap_duration: Callback<u64>,
 ) -> impl IntoView {
     let container_class = "flex flex-col";
     let input_container_class =
@@ -67,11 +139,10 @@ pub fn PlaybackGapDuration(
 
     let inc_dec_handler = move |op: Operation| {
         let step = 500;
-        set_gap_duration.update(|v| {
-            *v = match op {
-                Operation::Inc => *v + step,
-                Operation::Dec => *v - step,
-            }
+        let v = gap_duration.get_untracked();
+        set_gap_duration.run(match op {
+            Operation::Inc => v + step,
+            Operation::Dec => v.saturating_sub(step),
         });
     };
 
@@ -86,7 +157,7 @@ pub fn PlaybackGapDuration(
             target.set_value(&replaced);
         } else if let Ok(parsed) = val.parse::<f64>() {
             let float_mult = parsed * 1000.0;
-            set_gap_duration.set(float_mult as u64);
+            set_gap_duration.run(float_mult as u64);
         } else {
             val.pop();
             target.set_value(&val);
@@ -96,10 +167,10 @@ pub fn PlaybackGapDuration(
     view! {
         <div class=container_class>
             <label
-                for="speed-input"
+                for=input_id
                 class="block mb-1 text-xs font-medium text-slate-950 text-left select-none"
             >
-                "Silent gap (sec)"
+                {label}
             </label>
             <div class=input_container_class>
                 <button
@@ -129,7 +200,7 @@ pub fn PlaybackGapDuration(
                 </button>
                 <input
                     type="text"
-                    id="speed-input"
+                    id=input_id
                     data-input-counter
                     class="flex-shrink-0 text-slate-950 border-0 bg-transparent text-sm font-normal focus:outline-none focus:ring-0 max-w-[2.5rem] text-center [appearance:textfield] [&::-webkit-outer-spin-button]:appearance-none [&::-webkit-inner-spin-button]:appearance-none"
                     placeholder=""
@@ -166,6 +237,172 @@ pub fn PlaybackGapDuration(
     }
 }
 
+#[component]
+pub fn TimingModeSelect(
+    timing: Signal<TimingMode>,
+    #[prop(into)] set_timing: Callback<TimingMode>,
+) -> impl IntoView {
+    let change_handler = move |e: ev::Event| {
+        if let Ok(mode) = TimingMode::from_str(&event_target_value(&e)) {
+            set_timing.run(mode);
+        }
+    };
+
+    view! {
+        <div class="flex flex-col w-full">
+            <label
+                for="timing-mode-select"
+                class="block mb-1 text-xs font-medium text-slate-950 text-left select-none"
+            >
+                "Timing"
+            </label>
+            <select
+                id="timing-mode-select"
+                class="p-1 border-[1px] border-slate-200 rounded-full text-sm text-slate-950 bg-white"
+                on:change=change_handler
+            >
+                {TimingMode::iter()
+                    .map(|mode| {
+                        view! {
+                            <option value=mode.to_string() prop:selected=move || timing.get() == mode>
+                                {mode.label()}
+                            </option>
+                        }
+                    })
+                    .collect_view()}
+            </select>
+        </div>
+    }
+}
+
+#[component]
+pub fn GapDistributionSelect(
+    gap_distribution: Signal<GapDistribution>,
+    #[prop(into)] set_gap_distribution: Callback<GapDistribution>,
+) -> impl IntoView {
+    let change_handler = move |e: ev::Event| {
+        if let Ok(distribution) = GapDistribution::from_str(&event_target_value(&e)) {
+            set_gap_distribution.run(distribution);
+        }
+    };
+
+    view! {
+        <div class="flex flex-col w-full">
+            <label
+                for="gap-distribution-select"
+                class="block mb-1 text-xs font-medium text-slate-950 text-left select-none"
+            >
+                "Gap spread"
+            </label>
+            <select
+                id="gap-distribution-select"
+                class="p-1 border-[1px] border-slate-200 rounded-full text-sm text-slate-950 bg-white"
+                on:change=change_handler
+            >
+                {GapDistribution::iter()
+                    .map(|distribution| {
+                        view! {
+                            <option
+                                value=distribution.to_string()
+                                prop:selected=move || gap_distribution.get() == distribution
+                            >
+                                {format_filename(&distribution.to_string())}
+                            </option>
+                        }
+                    })
+                    .collect_view()}
+            </select>
+        </div>
+    }
+}
+
+#[component]
+pub fn EnvelopeControl(
+    envelope: ReadSignal<Envelope>,
+    set_envelope: WriteSignal<Envelope>,
+) -> impl IntoView {
+    let label_class = "block mb-1 text-xs font-medium text-slate-950 text-left select-none";
+    let input_class =
+        "w-full p-1 border-[1px] border-slate-200 rounded-full text-sm text-center text-slate-950";
+
+    let field = move |label: &'static str,
+                      get: fn(&Envelope) -> u64,
+                      set: fn(&mut Envelope, u64)| {
+        view! {
+            <div class="flex flex-col">
+                <label class=label_class>{label}</label>
+                <input
+                    type="number"
+                    min="0"
+                    step="0.1"
+                    class=input_class
+                    prop:value=move || envelope.with(|env| get(env) as f64 / 1000.0)
+                    on:change=move |e| {
+                        if let Ok(secs) = event_target_value(&e).parse::<f64>() {
+                            set_envelope.update(|env| set(env, (secs.max(0.0) * 1000.0) as u64));
+                        }
+                    }
+                />
+            </div>
+        }
+    };
+
+    view! {
+        <div class="grid grid-cols-2 gap-2 w-full">
+            {field("Fade in", |env| env.fade_in, |env, v| env.fade_in = v)}
+            {field("Fade out", |env| env.fade_out, |env, v| env.fade_out = v)}
+            {field("Crossfade", |env| env.crossfade, |env, v| env.crossfade = v)}
+            {field("Stop fade", |env| env.release, |env, v| env.release = v)}
+        </div>
+    }
+}
+
+#[component]
+pub fn HumanizeControl(
+    humanize: ReadSignal<Humanize>,
+    set_humanize: WriteSignal<Humanize>,
+) -> impl IntoView {
+    let label_class = "block mb-1 text-xs font-medium text-slate-950 text-left select-none";
+    let input_class =
+        "w-full p-1 border-[1px] border-slate-200 rounded-full text-sm text-center text-slate-950";
+
+    let field = move |label: &'static str,
+                      step: &'static str,
+                      get: fn(&Humanize) -> f32,
+                      set: fn(&mut Humanize, f32)| {
+        view! {
+            <div class="flex flex-col">
+                <label class=label_class>{label}</label>
+                <input
+                    type="number"
+                    min="0"
+                    step=step
+                    class=input_class
+                    prop:value=move || humanize.with(get)
+                    on:change=move |e| {
+                        if let Ok(val) = event_target_value(&e).parse::<f32>() {
+                            set_humanize.update(|h| set(h, val.max(0.0)));
+                        }
+                    }
+                />
+            </div>
+        }
+    };
+
+    view! {
+        <div class="grid grid-cols-2 gap-2 w-full">
+            {field("Pitch ±st", "0.5", |h| h.pitch, |h, v| h.pitch = v.min(12.0))}
+            {field("Volume ±dB", "1", |h| h.volume, |h, v| h.volume = v.min(24.0))}
+            {field(
+                "Timing (sec)",
+                "0.05",
+                |h| h.timing as f32 / 1000.0,
+                |h, v| h.timing = (v.min(5.0) * 1000.0) as u64,
+            )}
+        </div>
+    }
+}
+
 #[component]
 pub fn GridSizeControl(
     grid_rows_num: Signal<u16>,
@@ -196,10 +433,10 @@ pub fn GridSizeControl(
                     class=inc_dec_button_class
                     style=(
                         "opacity",
-                        move || { if grid_rows_num.get() == GRID_ROWS_MIN { "0.6" } else { "1" } },
+                        move || { if grid_rows_num.get() <= GRID_ROWS_MIN { "0.6" } else { "1" } },
                     )
 
-                    disabled=move || { grid_rows_num.get() == 0 }
+                    disabled=move || { grid_rows_num.get() <= GRID_ROWS_MIN }
                 >
                     <svg
                         class="w-2.5 h-2.5 text-slate-950 pointer-events-none"
@@ -238,8 +475,10 @@ pub fn GridSizeControl(
                     class=inc_dec_button_class
                     style=(
                         "opacity",
-                        move || { if grid_rows_num.get() == GRID_ROWS_MAX { "0.6" } else { "1" } },
+                        move || { if grid_rows_num.get() >= GRID_ROWS_MAX { "0.6" } else { "1" } },
                     )
+
+                    disabled=move || { grid_rows_num.get() >= GRID_ROWS_MAX }
                 >
                     <svg
                         class="w-2.5 h-2.5 text-slate-950 pointer-events-none"
@@ -262,6 +501,80 @@ pub fn GridSizeControl(
     }
 }
 
+#[component]
+pub fn LoopRangeControl(
+    loop_select: ReadSignal<bool>,
+    loop_select_handler: Callback<()>,
+    loop_range: Signal<Option<(usize, usize)>>,
+    clear_loop_handler: Callback<()>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let button_class = "flex items-center select-none p-2 -ml-2 -mb-2 hover:bg-slate-100 rounded text-sm text-slate-900 font-medium";
+
+    view! {
+        <div class="flex items-center justify-between w-full">
+            <button
+                class=button_class
+                on:click=move |_| {
+                    loop_select_handler.run(());
+                    set_open(false);
+                }
+            >
+                {move || {
+                    if loop_select.get() {
+                        "Cancel loop".to_string()
+                    } else if let Some((start, end)) = loop_range.get() {
+                        format!("Loop {}-{}", start + 1, end + 1)
+                    } else {
+                        "Loop range".to_string()
+                    }
+                }}
+            </button>
+            <Show when=move || loop_range.get().is_some() && !loop_select.get()>
+                <button
+                    class="p-1 -mb-2 hover:bg-slate-100 rounded"
+                    title="Clear loop"
+                    on:click=move |_| clear_loop_handler.run(())
+                >
+                    <XMark class="w-4 h-4 stroke-slate-900 stroke-2" />
+                </button>
+            </Show>
+        </div>
+    }
+}
+
+#[component]
+pub fn HistoryButtons(
+    can_undo: Signal<bool>,
+    can_redo: Signal<bool>,
+    undo_handler: Callback<()>,
+    redo_handler: Callback<()>,
+) -> impl IntoView {
+    let button_class = "p-2 cursor-pointer disabled:opacity-30 disabled:cursor-default";
+    let icon_class = "w-6 h-6 stroke-white";
+
+    view! {
+        <div class="flex items-center">
+            <button
+                class=button_class
+                title="Undo (Ctrl+Z)"
+                disabled=move || !can_undo.get()
+                on:click=move |_| undo_handler.run(())
+            >
+                <ArrowUturnLeft class=icon_class />
+            </button>
+            <button
+                class=button_class
+                title="Redo (Ctrl+Shift+Z)"
+                disabled=move || !can_redo.get()
+                on:click=move |_| redo_handler.run(())
+            >
+                <ArrowUturnRight class=icon_class />
+            </button>
+        </div>
+    }
+}
+
 #[component]
 pub fn EraseGridButton(
     erase_grid_handler: Callback<ev::MouseEvent>,
@@ -302,6 +615,72 @@ pub fn PresetsButton(
     }
 }
 
+#[component]
+pub fn EqualizerButton(
+    set_equalizer_visible: WriteSignal<bool>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let container_class =
+        "flex items-center cursor-pointer select-none p-2 -ml-2 -mt-2 hover:bg-slate-100 rounded w-full";
+
+    view! {
+        <button
+            class=container_class
+            on:click=move |_| {
+                set_equalizer_visible.update(|val| *val = !*val);
+                set_open.set(false);
+            }
+        >
+            <AdjustmentsHorizontal class="w-5 h-5 mr-2 stroke-slate-900 stroke-2" />
+            <span class="text-sm text-slate-900 font-medium">Equalizer</span>
+        </button>
+    }
+}
+
+#[component]
+pub fn TransitionsButton(
+    set_transitions_visible: WriteSignal<bool>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let container_class =
+        "flex items-center cursor-pointer select-none p-2 -ml-2 -mt-2 hover:bg-slate-100 rounded w-full";
+
+    view! {
+        <button
+            class=container_class
+            on:click=move |_| {
+                set_transitions_visible.update(|val| *val = !*val);
+                set_open.set(false);
+            }
+        >
+            <ArrowsRightLeft class="w-5 h-5 mr-2 stroke-slate-900 stroke-2" />
+            <span class="text-sm text-slate-900 font-medium">Transitions</span>
+        </button>
+    }
+}
+
+#[component]
+pub fn GeneratorButton(
+    set_generator_visible: WriteSignal<bool>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let container_class =
+        "flex items-center cursor-pointer select-none p-2 -ml-2 -mt-2 hover:bg-slate-100 rounded w-full";
+
+    view! {
+        <button
+            class=container_class
+            on:click=move |_| {
+                set_generator_visible.update(|val| *val = !*val);
+                set_open.set(false);
+            }
+        >
+            <Sparkles class="w-5 h-5 mr-2 stroke-slate-900 stroke-2" />
+            <span class="text-sm text-slate-900 font-medium">Generate</span>
+        </button>
+    }
+}
+
 #[component]
 pub fn ScheduleButton(
     set_schedule_visible: WriteSignal<bool>,
@@ -1,5 +1,5 @@
-use crate::components::button::Button;
-use crate::shared::{format_filename, Category, Sample};
+use crate::components::{button::Button, cell_settings::CellSettings};
+use crate::shared::{format_filename, Category, Cell, Sample};
 use ev::MouseEvent;
 use html::Audio;
 use leptos::{prelude::*, *};
@@ -15,6 +15,8 @@ pub fn SoundLibrary(
     sound_lib: HashMap<Category, Vec<Sample>>,
     edit_cell_idx: ReadSignal<Option<u16>>,
     is_cell_filled: Signal<bool>,
+    edit_cell: Signal<Option<Cell>>,
+    #[prop(into)] cell_change_handler: Callback<Cell>,
     #[prop(into)] sample_select_handler: Callback<Sample>,
     #[prop(into)] close_library_handler: Callback<MouseEvent>,
     #[prop(into)] clear_cell_handler: Callback<MouseEvent>,
@@ -151,7 +153,10 @@ pub fn SoundLibrary(
             <h1 class="absolute top-2 left-2 text-sm select-none">
                 You are editing cell #{edit_cell_idx}
             </h1>
-            <div class="mt-12 mb-20 px-4 md:px-8" on:dblclick=sample_double_click_handler>
+            <div class="mt-12 px-4 md:px-8">
+                <CellSettings edit_cell cell_change_handler />
+            </div>
+            <div class="mb-20 px-4 md:px-8" on:dblclick=sample_double_click_handler>
                 {render_view}
             </div>
             <ControlPanel
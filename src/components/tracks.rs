@@ -0,0 +1,82 @@
+use crate::shared::{Track, TRACKS_MAX};
+use leptos::{prelude::*, *};
+use leptos_heroicons::size_24::outline::XMark;
+
+#[component]
+pub fn TrackBar(
+    tracks: ReadSignal<Vec<Track>>,
+    active_track: ReadSignal<usize>,
+    set_active_track: WriteSignal<usize>,
+    #[prop(into)] add_track_handler: Callback<ev::MouseEvent>,
+    #[prop(into)] remove_track_handler: Callback<usize>,
+    #[prop(into)] track_volume_handler: Callback<f32>,
+) -> impl IntoView {
+    let container_class = "px-6 pb-2 flex items-center gap-2";
+    let tab_class = "relative h-8 min-w-8 px-3 rounded-full text-xs font-semibold select-none cursor-pointer shadow-sm bg-white/80 backdrop-blur-md";
+    let tab_active_class = "border-2 border-amber-400";
+    let input_class = "ml-auto w-24 h-1 bg-slate-950 rounded-lg appearance-none cursor-pointer";
+
+    let track_volume =
+        move || tracks.with(|t| t.get(active_track()).map_or(1.0, |track| track.volume));
+
+    let tabs = move || {
+        let tracks_num = tracks.with(|t| t.len());
+
+        (0..tracks_num)
+            .map(|idx| {
+                view! {
+                    <button
+                        class=move || {
+                            format!(
+                                "{tab_class} {}",
+                                if idx == active_track() { tab_active_class } else { "" },
+                            )
+                        }
+                        on:click=move |_| set_active_track.set(idx)
+                    >
+                        {idx + 1}
+                        <Show when=move || { idx == active_track() && tracks_num > 1 }>
+                            <span
+                                class="absolute -top-1 -right-1 bg-white rounded-full shadow"
+                                on:click=move |e| {
+                                    e.stop_propagation();
+                                    remove_track_handler.run(idx);
+                                }
+                            >
+                                <XMark class="w-3 h-3 stroke-slate-950 stroke-2" />
+                            </span>
+                        </Show>
+                    </button>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class=container_class>
+            {tabs}
+            <button
+                class=move || {
+                    format!(
+                        "{tab_class} {}",
+                        if tracks.with(|t| t.len()) >= TRACKS_MAX { "hidden" } else { "" },
+                    )
+                }
+                on:click=move |e| add_track_handler.run(e)
+            >
+                "+"
+            </button>
+            <input
+                type="range"
+                title="Track volume"
+                min=0
+                max=100
+                class=input_class
+                prop:value=move || { track_volume() * 100.0 }
+                on:change=move |e| {
+                    track_volume_handler.run(event_target_value(&e).parse::<f32>().unwrap() / 100.0);
+                }
+            />
+        </div>
+    }
+}
@@ -0,0 +1,96 @@
+use crate::components::button::Button;
+use crate::shared::{format_filename, Category, Transitions};
+use leptos::prelude::*;
+
+#[component]
+pub fn TransitionsPanel(
+    transitions_visible: ReadSignal<bool>,
+    set_transitions_visible: WriteSignal<bool>,
+    transitions: ReadSignal<Transitions>,
+    set_transitions: WriteSignal<Transitions>,
+) -> impl IntoView {
+    let container_class =
+        "absolute top-0 right-0 bottom-auto left-0 min-h-screen w-screen bg-white p-2";
+    let header_class = "p-1 text-xs font-medium text-slate-950 select-none";
+    let input_class =
+        "w-14 p-1 border-[1px] border-slate-200 rounded-full text-sm text-center text-slate-950";
+
+    let header = |category: Category| {
+        format!(
+            "{} {}",
+            category.get_emoji(),
+            format_filename(&category.to_string())
+        )
+    };
+
+    view! {
+        <div
+            class=container_class
+            style:display=move || { if transitions_visible() { "block" } else { "none" } }
+        >
+            <p class="mb-4 text-sm text-slate-950 select-none">
+                "Chance of the column category following the row category in the Chain order"
+            </p>
+            <div class="overflow-x-auto mb-6">
+                <table>
+                    <thead>
+                        <tr>
+                            <th></th>
+                            {Category::iter()
+                                .map(|to| view! { <th class=header_class>{header(to)}</th> })
+                                .collect_view()}
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {Category::iter()
+                            .map(|from| {
+                                view! {
+                                    <tr>
+                                        <th class=format!("{header_class} text-left")>
+                                            {header(from)}
+                                        </th>
+                                        {Category::iter()
+                                            .map(|to| {
+                                                view! {
+                                                    <td class="p-1 text-center">
+                                                        <input
+                                                            type="number"
+                                                            min="0"
+                                                            max="10"
+                                                            step="0.5"
+                                                            class=input_class
+                                                            prop:value=move || {
+                                                                transitions.with(|t| t.weight(from, to))
+                                                            }
+                                                            on:change=move |e| {
+                                                                if let Ok(weight) = event_target_value(&e)
+                                                                    .parse::<f32>()
+                                                                {
+                                                                    set_transitions
+                                                                        .update(|t| t.set_weight(from, to, weight));
+                                                                }
+                                                            }
+                                                        />
+                                                    </td>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </tr>
+                                }
+                            })
+                            .collect_view()}
+                    </tbody>
+                </table>
+            </div>
+
+            <div class="fixed bottom-[4%] w-screen h-[56px] left-0">
+                <div class="w-60 h-[100%] mx-auto flex items-center justify-center gap-2">
+                    <Button on:click=move |_| set_transitions(Transitions::default())>Reset</Button>
+                    <Button on:click=move |_| {
+                        set_transitions_visible.update(|val| { *val = !*val })
+                    }>Close</Button>
+                </div>
+            </div>
+        </div>
+    }
+}
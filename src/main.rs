@@ -2,6 +2,7 @@
 
 mod app;
 mod components;
+mod playback;
 mod shared;
 
 use app::*;
@@ -0,0 +1,92 @@
+use crate::shared::{Equalizer, Limiter};
+use wasm_bindgen::JsValue;
+use web_sys::{
+    AudioContext, AudioNode, BiquadFilterNode, BiquadFilterType, DynamicsCompressorNode, GainNode,
+};
+
+const LIMIT_RATIO: f32 = 20.0;
+
+// NOTE: Master EQ between the mix and the speakers: low shelf, peaking mid, high cut
+#[derive(Clone)]
+pub struct EqChain {
+    low_shelf: BiquadFilterNode,
+    mid: BiquadFilterNode,
+    high_cut: BiquadFilterNode,
+}
+
+impl EqChain {
+    pub fn new(ctx: &AudioContext, destination: &AudioNode) -> Result<Self, JsValue> {
+        let low_shelf = ctx.create_biquad_filter()?;
+        low_shelf.set_type(BiquadFilterType::Lowshelf);
+        let mid = ctx.create_biquad_filter()?;
+        mid.set_type(BiquadFilterType::Peaking);
+        let high_cut = ctx.create_biquad_filter()?;
+        high_cut.set_type(BiquadFilterType::Lowpass);
+        high_cut.q().set_value(std::f32::consts::FRAC_1_SQRT_2);
+
+        low_shelf.connect_with_audio_node(&mid)?;
+        mid.connect_with_audio_node(&high_cut)?;
+        high_cut.connect_with_audio_node(destination)?;
+
+        let chain = Self {
+            low_shelf,
+            mid,
+            high_cut,
+        };
+        chain.apply(&Equalizer::default());
+        Ok(chain)
+    }
+
+    pub fn input(&self) -> &AudioNode {
+        &self.low_shelf
+    }
+
+    pub fn apply(&self, eq: &Equalizer) {
+        self.low_shelf.frequency().set_value(eq.low_shelf_freq);
+        self.low_shelf.gain().set_value(eq.low_shelf_gain);
+        self.mid.frequency().set_value(eq.mid_freq);
+        self.mid.gain().set_value(eq.mid_gain);
+        self.mid.q().set_value(eq.mid_q);
+        self.high_cut.frequency().set_value(eq.high_cut_freq);
+    }
+}
+
+// NOTE: Last stage before the speakers, a compressor set as hard as the node allows. It is
+// never bypassed, turning the limiter off only lifts the ceiling to the max volume
+#[derive(Clone)]
+pub struct LimiterStage {
+    compressor: DynamicsCompressorNode,
+    trim: GainNode,
+}
+
+impl LimiterStage {
+    pub fn new(ctx: &AudioContext, destination: &AudioNode) -> Result<Self, JsValue> {
+        let compressor = ctx.create_dynamics_compressor()?;
+        compressor.knee().set_value(0.0);
+        compressor.attack().set_value(0.001);
+        compressor.release().set_value(0.1);
+        let trim = ctx.create_gain()?;
+
+        compressor.connect_with_audio_node(&trim)?;
+        trim.connect_with_audio_node(destination)?;
+
+        let stage = Self { compressor, trim };
+        stage.apply(&Limiter::default());
+        Ok(stage)
+    }
+
+    pub fn input(&self) -> &AudioNode {
+        &self.compressor
+    }
+
+    pub fn apply(&self, limiter: &Limiter) {
+        let threshold = limiter.threshold();
+        self.compressor.threshold().set_value(threshold);
+        self.compressor.ratio().set_value(LIMIT_RATIO);
+
+        // NOTE: The node adds makeup gain of 0.6 times the level it takes off a full scale
+        // signal, trimming it back keeps peaks at the ceiling
+        let makeup = -0.6 * threshold * (1.0 - 1.0 / LIMIT_RATIO);
+        self.trim.gain().set_value(10f32.powf(-makeup / 20.0));
+    }
+}
@@ -0,0 +1,796 @@
+use crate::playback::dsp::{EqChain, LimiterStage};
+use crate::shared::{
+    next_cell, Category, Cell, Channel, Envelope, Equalizer, GapDistribution, Humanize, Limiter,
+    Mixer, PlaybackOrder, PlaybackState, RowOp, Sample, TimingMode, Track, Transitions,
+    VoiceParams, MIN_STEP,
+};
+use leptos::logging;
+use leptos::prelude::{set_interval_with_handle, IntervalHandle};
+use rand::{rng, Rng};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, AudioScheduledSourceNode,
+    GainNode, Response,
+};
+
+// NOTE: Cells are put on the audio clock this far ahead of the current time
+const LOOKAHEAD: f64 = 1.0;
+const TICK_INTERVAL: Duration = Duration::from_millis(25);
+const START_DELAY: f64 = 0.05;
+// NOTE: Even an instant stop ramps down this long to avoid a click
+const DECLICK: f64 = 0.02;
+const HISTORY_MAX: usize = 64;
+
+struct Voice {
+    source: AudioBufferSourceNode,
+    start: f64,
+    end: f64,
+}
+
+struct Preview {
+    filepath: String,
+    voice: Voice,
+}
+
+struct Sequencer {
+    id: String,
+    output: GainNode,
+    // NOTE: One gain per category between the voices and the track output, set by the mixer
+    buses: HashMap<Category, GainNode>,
+    volume: f32,
+    grid: Vec<Option<Cell>>,
+    gap_min: f64,
+    gap_max: f64,
+    gap_distribution: GapDistribution,
+    timing: TimingMode,
+    step: f64,
+    order: PlaybackOrder,
+    range: (usize, usize),
+    channel: Channel,
+    transitions: Rc<RefCell<Transitions>>,
+    // NOTE: Cells not yet played in the current shuffle round
+    bag: Vec<usize>,
+    cursor: usize,
+    next_time: f64,
+    marks: VecDeque<(f64, usize)>,
+    // NOTE: Reached cells, newest last, so skipping back follows the random order too
+    history: Vec<usize>,
+    voices: Vec<Voice>,
+}
+
+impl Sequencer {
+    fn new(engine: &PlaybackEngine, id: String) -> Result<Self, JsValue> {
+        let output = engine.ctx.create_gain()?;
+        output.connect_with_audio_node(&engine.master)?;
+
+        let mixer = engine.mixer.borrow();
+        let mut buses = HashMap::new();
+        for category in Category::iter() {
+            let bus = engine.ctx.create_gain()?;
+            bus.gain().set_value(mixer.strip(category).volume);
+            bus.connect_with_audio_node(&output)?;
+            buses.insert(category, bus);
+        }
+
+        Ok(Self {
+            id,
+            output,
+            buses,
+            volume: 1.0,
+            grid: Vec::new(),
+            gap_min: 0.0,
+            gap_max: 0.0,
+            gap_distribution: GapDistribution::default(),
+            timing: TimingMode::default(),
+            step: 0.5,
+            order: PlaybackOrder::default(),
+            range: (0, 0),
+            channel: Channel::default(),
+            transitions: engine.transitions.clone(),
+            bag: Vec::new(),
+            cursor: 0,
+            next_time: engine.ctx.current_time() + START_DELAY,
+            marks: VecDeque::new(),
+            history: Vec::new(),
+            voices: Vec::new(),
+        })
+    }
+
+    fn apply(&mut self, engine: &PlaybackEngine, track: Track) {
+        if self.cursor >= track.grid_data.len() {
+            self.cursor = 0;
+        }
+        let range = track.loop_bounds();
+        if self.range != range || self.order != track.playback_order {
+            self.bag.clear();
+        }
+
+        let (gap_min, gap_max) = track.gap_range();
+        self.grid = track.grid_data;
+        self.load(engine);
+        self.gap_min = gap_min as f64 / 1000.0;
+        self.gap_max = gap_max as f64 / 1000.0;
+        self.gap_distribution = track.gap_distribution;
+        self.timing = track.timing;
+        self.step = track.step_interval as f64 / 1000.0;
+        self.order = track.playback_order;
+        self.range = range;
+        self.channel = track.channel;
+        self.volume = track.volume;
+        self.output.gain().set_value(track.volume);
+    }
+
+    fn load(&self, engine: &PlaybackEngine) {
+        let library = engine.library.borrow();
+        for cell in self.grid.iter().flatten() {
+            for sample in cell.pool_members(&library) {
+                engine.load(&sample.filepath);
+            }
+        }
+    }
+
+    fn restart(&mut self, cell: usize, when: f64) {
+        self.silence();
+        // NOTE: Drop what is left of a stop fade
+        let gain = self.output.gain();
+        let _ = gain.cancel_scheduled_values(0.0);
+        gain.set_value(self.volume);
+        self.cursor = if cell < self.grid.len() { cell } else { 0 };
+        self.next_time = when;
+        self.bag.retain(|&idx| idx != self.cursor);
+    }
+
+    fn silence(&mut self) {
+        for voice in self.voices.drain(..) {
+            let _ = AudioScheduledSourceNode::stop(&voice.source);
+        }
+        self.marks.clear();
+    }
+
+    fn release(&mut self, now: f64, release: f64) {
+        let gain = self.output.gain();
+        let _ = gain.cancel_scheduled_values(now);
+        let _ = gain.set_value_at_time(gain.value(), now);
+        let _ = gain.linear_ramp_to_value_at_time(0.0, now + release);
+
+        for voice in self.voices.drain(..) {
+            let _ = if voice.start > now {
+                AudioScheduledSourceNode::stop(&voice.source)
+            } else {
+                AudioScheduledSourceNode::stop_with_when(&voice.source, now + release)
+            };
+        }
+        self.marks.clear();
+    }
+
+    fn next_cell(&mut self, current: usize) -> usize {
+        next_cell(
+            &self.grid,
+            self.range,
+            self.order,
+            &self.transitions.borrow(),
+            current,
+            &mut self.bag,
+            &mut rng(),
+        )
+    }
+
+    fn next_gap(&self) -> f64 {
+        self.gap_distribution
+            .sample(self.gap_min, self.gap_max, &mut rng())
+    }
+
+    // NOTE: Schedules the cells that fall into the lookahead window and returns the
+    // latest cell whose start time has been reached
+    fn schedule(&mut self, engine: &PlaybackEngine, now: f64) -> Option<usize> {
+        let len = self.grid.len();
+
+        while len > 0 && self.next_time < now + LOOKAHEAD {
+            if self.cursor >= len {
+                self.cursor = 0;
+            }
+            let cell = self.cursor;
+            let start = self.next_time.max(now);
+
+            let (duration, gap) = match &self.grid[cell] {
+                Some(item)
+                    if item.probability < 1.0 && rng().random::<f32>() >= item.probability =>
+                {
+                    let gap = match item.gap_override {
+                        Some(gap_override) => gap_override as f64 / 1000.0,
+                        None => self.next_gap(),
+                    };
+                    (0.0, gap)
+                }
+                Some(item) => {
+                    let library = engine.library.borrow();
+                    let members = item.pool_members(&library);
+                    let sample = members[rng().random_range(0..members.len())];
+                    let buffer = engine.buffers.borrow().get(&sample.filepath).cloned();
+                    let Some(buffer) = buffer else {
+                        // NOTE: Hold the sequence until the sample is decoded
+                        engine.load(&sample.filepath);
+                        self.next_time = start;
+                        break;
+                    };
+
+                    let envelope = *engine.envelope.borrow();
+                    let (rate, gain, delay) = engine.humanize.borrow().draw(&mut rng());
+                    let params = VoiceParams::for_cell(item, buffer.duration(), &envelope);
+                    let params = VoiceParams {
+                        rate: params.rate * f64::from(rate),
+                        gain: params.gain * gain,
+                        pan: engine.mixer.borrow().pan(item, self.channel),
+                        ..params
+                    };
+                    let params = match (item.looping, item.excerpt) {
+                        (Some(looping), _) => {
+                            params.looped(looping, sample.loop_points, buffer.duration())
+                        }
+                        (None, Some(excerpt)) => {
+                            params.excerpt(excerpt as f64 / 1000.0, &mut rng())
+                        }
+                        (None, None) => params,
+                    };
+                    let repeat = item.repeat.max(1);
+                    // NOTE: The delay shifts this trigger only, the next cell keeps its time
+                    let onset = start + delay;
+                    let mut at = onset;
+                    // NOTE: Repeats run back to back, so only the outer edges get faded
+                    for idx in 0..repeat {
+                        let voice_params = VoiceParams {
+                            fade_in: if idx == 0 { params.fade_in } else { 0.0 },
+                            fade_out: if idx == repeat - 1 {
+                                params.fade_out
+                            } else {
+                                0.0
+                            },
+                            ..params.clone()
+                        };
+                        let bus = &self.buses[&item.sample.category];
+                        match engine.start_voice(&buffer, bus, at, &voice_params) {
+                            Ok(voice) => self.voices.push(voice),
+                            Err(err) => logging::error!("{:?}", err),
+                        }
+                        at += params.span();
+                    }
+
+                    let gap = match item.gap_override {
+                        Some(gap_override) => gap_override as f64 / 1000.0,
+                        None => self.next_gap(),
+                    };
+                    // NOTE: The crossfade pulls the next cell in, it overlaps this one when
+                    // it is longer than the gap
+                    (at - onset, gap - envelope.crossfade as f64 / 1000.0)
+                }
+                None => (0.0, self.next_gap()),
+            };
+
+            self.marks.push_back((start, cell));
+            // NOTE: In step mode onsets keep a fixed pace, long samples overlap the next step
+            self.next_time = start
+                + match self.timing {
+                    TimingMode::Gap => duration + gap,
+                    TimingMode::Step => self.step,
+                }
+                .max(MIN_STEP);
+            self.cursor = self.next_cell(cell);
+        }
+
+        self.voices.retain(|voice| voice.end > now);
+
+        let mut reached = None;
+        while let Some(&(time, cell)) = self.marks.front() {
+            if time > now {
+                break;
+            }
+            reached = Some(cell);
+            self.marks.pop_front();
+            self.history.push(cell);
+        }
+        if self.history.len() > HISTORY_MAX {
+            self.history.drain(..self.history.len() - HISTORY_MAX);
+        }
+        reached
+    }
+
+    fn is_sounding(&self, now: f64) -> bool {
+        self.voices
+            .iter()
+            .any(|voice| voice.start <= now && now < voice.end)
+    }
+
+    // NOTE: Cells of a deleted row give way to the row that took their place
+    fn remap(&mut self, op: RowOp, row: usize, row_size: usize) {
+        let fallback = (row * row_size).min(self.grid.len().saturating_sub(row_size + 1));
+        let remap = |idx: usize| op.remap(idx, row, row_size);
+        self.cursor = remap(self.cursor).unwrap_or(fallback);
+        self.history = self.history.iter().filter_map(|&idx| remap(idx)).collect();
+        self.bag = self.bag.iter().filter_map(|&idx| remap(idx)).collect();
+        self.marks = self
+            .marks
+            .iter()
+            .filter_map(|&(time, idx)| Some((time, remap(idx)?)))
+            .collect();
+    }
+
+    fn skip(&mut self, forward: bool, when: f64) -> usize {
+        let current = self.history.pop().unwrap_or(self.cursor);
+        let target = if forward {
+            self.history.push(current);
+            self.next_cell(current)
+        } else {
+            // NOTE: With nothing earlier the current cell starts over
+            self.history.pop().unwrap_or(current)
+        };
+        self.restart(target, when);
+        self.cursor
+    }
+}
+
+#[derive(Clone)]
+pub struct PlaybackEngine {
+    ctx: AudioContext,
+    master: GainNode,
+    eq: EqChain,
+    limiter: LimiterStage,
+    volume: Rc<RefCell<f32>>,
+    max_volume: Rc<RefCell<f32>>,
+    buffers: Rc<RefCell<HashMap<String, AudioBuffer>>>,
+    loading: Rc<RefCell<HashSet<String>>>,
+    sequencers: Rc<RefCell<Vec<Sequencer>>>,
+    library: Rc<RefCell<HashMap<Category, Vec<Sample>>>>,
+    preview: Rc<RefCell<Option<Preview>>>,
+    envelope: Rc<RefCell<Envelope>>,
+    humanize: Rc<RefCell<Humanize>>,
+    transitions: Rc<RefCell<Transitions>>,
+    mixer: Rc<RefCell<Mixer>>,
+    state: Rc<RefCell<PlaybackState>>,
+    ticker: Rc<RefCell<Option<IntervalHandle>>>,
+    on_cell: Rc<dyn Fn(usize, usize)>,
+    on_state: Rc<dyn Fn(PlaybackState)>,
+}
+
+impl PlaybackEngine {
+    pub fn new(
+        on_cell: impl Fn(usize, usize) + 'static,
+        on_state: impl Fn(PlaybackState) + 'static,
+    ) -> Self {
+        let ctx = AudioContext::new().expect("Failed to create audio context");
+        let master = ctx
+            .create_gain()
+            .expect("Failed to create master gain node");
+        let limiter =
+            LimiterStage::new(&ctx, &ctx.destination()).expect("Failed to create limiter");
+        let eq = EqChain::new(&ctx, limiter.input()).expect("Failed to create EQ chain");
+        master
+            .connect_with_audio_node(eq.input())
+            .expect("Failed to connect master gain node");
+
+        Self {
+            ctx,
+            master,
+            eq,
+            limiter,
+            volume: Rc::new(RefCell::new(1.0)),
+            max_volume: Rc::new(RefCell::new(1.0)),
+            buffers: Rc::default(),
+            loading: Rc::default(),
+            sequencers: Rc::default(),
+            library: Rc::default(),
+            preview: Rc::default(),
+            envelope: Rc::default(),
+            humanize: Rc::default(),
+            transitions: Rc::default(),
+            mixer: Rc::default(),
+            state: Rc::default(),
+            ticker: Rc::default(),
+            on_cell: Rc::new(on_cell),
+            on_state: Rc::new(on_state),
+        }
+    }
+
+    // NOTE: Whatever a preset or schedule asks for, the master never goes past the max volume
+    pub fn set_volume(&self, volume: f32) {
+        *self.volume.borrow_mut() = volume;
+        self.master
+            .gain()
+            .set_value(volume.min(*self.max_volume.borrow()));
+    }
+
+    pub fn set_limiter(&self, limiter: &Limiter) {
+        self.limiter.apply(limiter);
+        *self.max_volume.borrow_mut() = limiter.max_volume.clamp(0.0, 1.0);
+        let volume = *self.volume.borrow();
+        self.set_volume(volume);
+    }
+
+    pub fn set_equalizer(&self, equalizer: &Equalizer) {
+        self.eq.apply(equalizer);
+    }
+
+    pub fn set_envelope(&self, envelope: Envelope) {
+        *self.envelope.borrow_mut() = envelope;
+    }
+
+    pub fn set_humanize(&self, humanize: Humanize) {
+        *self.humanize.borrow_mut() = humanize;
+    }
+
+    pub fn set_transitions(&self, transitions: Transitions) {
+        *self.transitions.borrow_mut() = transitions;
+    }
+
+    // NOTE: Volumes apply right away, routing takes effect from the next scheduled cell
+    pub fn set_mixer(&self, mixer: Mixer) {
+        for seq in self.sequencers.borrow().iter() {
+            for (category, bus) in seq.buses.iter() {
+                bus.gain().set_value(mixer.strip(*category).volume);
+            }
+        }
+        *self.mixer.borrow_mut() = mixer;
+    }
+
+    // NOTE: Variant and category cells need the library to know their members
+    pub fn set_library(&self, library: HashMap<Category, Vec<Sample>>) {
+        *self.library.borrow_mut() = library;
+        for seq in self.sequencers.borrow().iter() {
+            seq.load(self);
+        }
+    }
+
+    // NOTE: Sequencers are matched to tracks by id, so reordering or removing a track
+    // keeps the position of the others
+    pub fn set_tracks(&self, tracks: Vec<Track>) {
+        let mut previous = self.sequencers.take();
+        let mut sequencers = Vec::with_capacity(tracks.len());
+
+        for track in tracks {
+            let existing = previous
+                .iter()
+                .position(|seq| seq.id == track.id)
+                .map(|idx| previous.swap_remove(idx));
+            let seq = match existing {
+                Some(seq) => Ok(seq),
+                None => Sequencer::new(self, track.id.clone()),
+            };
+
+            match seq {
+                Ok(mut seq) => {
+                    seq.apply(self, track);
+                    sequencers.push(seq);
+                }
+                Err(err) => logging::error!("{:?}", err),
+            }
+        }
+
+        for mut seq in previous {
+            seq.silence();
+            let _ = seq.output.disconnect();
+        }
+
+        *self.sequencers.borrow_mut() = sequencers;
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        *self.state.borrow()
+    }
+
+    pub fn play(&self, cells: &[usize]) {
+        self.resume_context();
+        self.stop_preview();
+
+        let when = self.ctx.current_time() + START_DELAY;
+        for (idx, seq) in self.sequencers.borrow_mut().iter_mut().enumerate() {
+            let (start, end) = seq.range;
+            let cell = cells.get(idx).copied().unwrap_or(0);
+            seq.history.clear();
+            seq.restart(
+                if cell < start || cell > end {
+                    start
+                } else {
+                    cell
+                },
+                when,
+            );
+        }
+
+        self.set_state(PlaybackState::InGap);
+        self.start_ticker();
+        self.tick();
+    }
+
+    // NOTE: Sounding cells fade out over the release time instead of being cut
+    pub fn stop(&self) {
+        self.stop_ticker();
+        if self.state() == PlaybackState::Paused {
+            self.resume_context();
+        }
+
+        let now = self.ctx.current_time();
+        let release = (self.envelope.borrow().release as f64 / 1000.0).max(DECLICK);
+        for seq in self.sequencers.borrow_mut().iter_mut() {
+            seq.release(now, release);
+        }
+        self.set_state(PlaybackState::Idle);
+    }
+
+    // NOTE: Suspending the context freezes the audio clock, so voices and gaps resume
+    // exactly where they were
+    pub fn pause(&self) {
+        if !matches!(self.state(), PlaybackState::Playing | PlaybackState::InGap) {
+            return;
+        }
+
+        self.stop_ticker();
+        match self.ctx.suspend() {
+            Ok(promise) => wasm_bindgen_futures::spawn_local(async move {
+                if let Err(err) = JsFuture::from(promise).await {
+                    logging::error!("{:?}", err);
+                }
+            }),
+            Err(err) => logging::error!("{:?}", err),
+        }
+        self.set_state(PlaybackState::Paused);
+    }
+
+    pub fn resume(&self) {
+        if self.state() != PlaybackState::Paused {
+            return;
+        }
+
+        self.resume_context();
+        self.set_state(PlaybackState::InGap);
+        self.start_ticker();
+        self.tick();
+    }
+
+    // NOTE: Jump to a cell picked in the grid while the sequence is running or paused
+    pub fn seek(&self, track: usize, cell: usize) {
+        if self.state() == PlaybackState::Idle {
+            return;
+        }
+
+        let when = self.ctx.current_time() + START_DELAY;
+        if let Some(seq) = self.sequencers.borrow_mut().get_mut(track) {
+            seq.restart(cell, when);
+        }
+        self.tick();
+    }
+
+    // NOTE: A row op moves cells under a running sequence, which keeps playing the cells it
+    // was at instead of the indices
+    pub fn remap(&self, track: usize, op: RowOp, row: usize, row_size: usize) {
+        if self.state() == PlaybackState::Idle {
+            return;
+        }
+
+        if let Some(seq) = self.sequencers.borrow_mut().get_mut(track) {
+            seq.remap(op, row, row_size);
+        }
+    }
+
+    pub fn skip(&self, forward: bool) {
+        if self.state() == PlaybackState::Idle {
+            return;
+        }
+
+        let when = self.ctx.current_time() + START_DELAY;
+        let targets = self
+            .sequencers
+            .borrow_mut()
+            .iter_mut()
+            .map(|seq| seq.skip(forward, when))
+            .collect::<Vec<_>>();
+
+        // NOTE: Report right away, a paused clock would not reach the new cells
+        for (track, cell) in targets.into_iter().enumerate() {
+            (self.on_cell)(track, cell);
+        }
+        self.tick();
+    }
+
+    pub fn preview(&self, filepath: &str) {
+        self.resume_context();
+
+        let now = self.ctx.current_time();
+        let is_same_sounding = self
+            .preview
+            .borrow()
+            .as_ref()
+            .is_some_and(|p| p.filepath == filepath && p.voice.end > now);
+        self.stop_preview();
+        if is_same_sounding {
+            return;
+        }
+
+        let engine = self.clone();
+        let filepath = filepath.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some(buffer) = engine.buffer(&filepath).await else {
+                return;
+            };
+            let when = engine.ctx.current_time();
+            let params = VoiceParams::full(buffer.duration());
+            match engine.start_voice(&buffer, &engine.master, when, &params) {
+                Ok(voice) => *engine.preview.borrow_mut() = Some(Preview { filepath, voice }),
+                Err(err) => logging::error!("{:?}", err),
+            }
+        });
+    }
+
+    pub fn stop_preview(&self) {
+        if let Some(preview) = self.preview.borrow_mut().take() {
+            let _ = AudioScheduledSourceNode::stop(&preview.voice.source);
+        }
+    }
+
+    fn resume_context(&self) {
+        // NOTE: Browsers keep a fresh context suspended until a user gesture
+        if let Ok(promise) = self.ctx.resume() {
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(err) = JsFuture::from(promise).await {
+                    logging::error!("{:?}", err);
+                }
+            });
+        }
+    }
+
+    fn load(&self, filepath: &str) {
+        if self.buffers.borrow().contains_key(filepath)
+            || !self.loading.borrow_mut().insert(filepath.to_string())
+        {
+            return;
+        }
+
+        let engine = self.clone();
+        let filepath = filepath.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            engine.buffer(&filepath).await;
+            engine.loading.borrow_mut().remove(&filepath);
+        });
+    }
+
+    async fn buffer(&self, filepath: &str) -> Option<AudioBuffer> {
+        let cached = self.buffers.borrow().get(filepath).cloned();
+        if cached.is_some() {
+            return cached;
+        }
+
+        match fetch_buffer(&self.ctx, filepath).await {
+            Ok(buffer) => {
+                self.buffers
+                    .borrow_mut()
+                    .insert(filepath.to_string(), buffer.clone());
+                Some(buffer)
+            }
+            Err(err) => {
+                logging::error!("{:?}", err);
+                None
+            }
+        }
+    }
+
+    fn start_voice(
+        &self,
+        buffer: &AudioBuffer,
+        destination: &AudioNode,
+        when: f64,
+        params: &VoiceParams,
+    ) -> Result<Voice, JsValue> {
+        let source = self.ctx.create_buffer_source()?;
+        source.set_buffer(Some(buffer));
+        source.playback_rate().set_value(params.rate as f32);
+        if let Some((start, end)) = params.looping {
+            source.set_loop(true);
+            source.set_loop_start(start);
+            source.set_loop_end(end);
+        }
+
+        let gain = self.ctx.create_gain()?;
+        let end = when + params.span();
+        // NOTE: Fades are squeezed to fit the voice when they are longer than it
+        let scale = (params.span() / (params.fade_in + params.fade_out)).min(1.0);
+        let fade_in = params.fade_in * scale;
+        let fade_out = params.fade_out * scale;
+        if fade_in > 0.0 {
+            gain.gain().set_value_at_time(0.0, when)?;
+            gain.gain()
+                .linear_ramp_to_value_at_time(params.gain, when + fade_in)?;
+        } else {
+            gain.gain().set_value_at_time(params.gain, when)?;
+        }
+        if fade_out > 0.0 {
+            gain.gain().set_value_at_time(params.gain, end - fade_out)?;
+            gain.gain().linear_ramp_to_value_at_time(0.0, end)?;
+        }
+        source.connect_with_audio_node(&gain)?;
+        if params.pan != 0.0 {
+            let panner = self.ctx.create_stereo_panner()?;
+            panner.pan().set_value(params.pan);
+            gain.connect_with_audio_node(&panner)?;
+            panner.connect_with_audio_node(destination)?;
+        } else {
+            gain.connect_with_audio_node(destination)?;
+        }
+
+        source.start_with_when_and_grain_offset_and_grain_duration(
+            when,
+            params.offset,
+            params.length,
+        )?;
+
+        Ok(Voice {
+            source,
+            start: when,
+            end,
+        })
+    }
+
+    fn start_ticker(&self) {
+        if self.ticker.borrow().is_some() {
+            return;
+        }
+
+        let engine = self.clone();
+        match set_interval_with_handle(move || engine.tick(), TICK_INTERVAL) {
+            Ok(handle) => *self.ticker.borrow_mut() = Some(handle),
+            Err(err) => logging::error!("{:?}", err),
+        }
+    }
+
+    fn stop_ticker(&self) {
+        if let Some(handle) = self.ticker.borrow_mut().take() {
+            handle.clear();
+        }
+    }
+
+    fn set_state(&self, state: PlaybackState) {
+        if self.state.replace(state) != state {
+            (self.on_state)(state);
+        }
+    }
+
+    fn tick(&self) {
+        let now = self.ctx.current_time();
+        let mut sounding = false;
+        let reached = self
+            .sequencers
+            .borrow_mut()
+            .iter_mut()
+            .map(|seq| {
+                let reached = seq.schedule(self, now);
+                sounding |= seq.is_sounding(now);
+                reached
+            })
+            .collect::<Vec<_>>();
+
+        for (track, cell) in reached.into_iter().enumerate() {
+            if let Some(cell) = cell {
+                (self.on_cell)(track, cell);
+            }
+        }
+
+        if matches!(self.state(), PlaybackState::Playing | PlaybackState::InGap) {
+            self.set_state(if sounding {
+                PlaybackState::Playing
+            } else {
+                PlaybackState::InGap
+            });
+        }
+    }
+}
+
+async fn fetch_buffer(ctx: &AudioContext, filepath: &str) -> Result<AudioBuffer, JsValue> {
+    let resp_val = JsFuture::from(web_sys::window().unwrap().fetch_with_str(filepath)).await?;
+    let resp: Response = resp_val.dyn_into()?;
+    let array_buffer = JsFuture::from(resp.array_buffer()?).await?;
+    let decoded = JsFuture::from(ctx.decode_audio_data(&array_buffer.dyn_into()?)?).await?;
+
+    decoded.dyn_into()
+}
@@ -1,142 +1,513 @@
-use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc, Weekday};
-use core::{fmt, str};
-use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use chrono::{NaiveDateTime, NaiveTime, Weekday};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 
-pub const EMPTY_SOUND: &str = "data:audio/mp3;base64,/+MYxAALM2H8CABNSR8lJeb1//ZLXL3GxwA////Gfvrf/xEGRl3ZCQcBk9PXDQQnx2IAMLJ3sQYgITEFNRTMuOTguMgAAAAA/+MYxAAKu2IcCABNSR/B/IvkRlkrv84Pyb////7n8/c/lr/fJKmETd2jQJys1AEVDNFHQFpMdayYgpqKZlxycFxkAAAAAAAA/+MYxAAKe2YcEACTTAGpb/f/3S3S6O/7W/3///39ay7IZqJYLGUjmMC5G1Nc8iFSopqzJM8YMJiCmopmXHJwXGQAAAAAAAAA/+MYxAAKC2YgEACTTAG+3/9/X3/Rfomn+///qv0N6Is4txSqUMFVEcRLCVlx3prPbiPEc32mIKaimZccnBcZAAAAAAAAAAAA/+MYxAAKg2IgEABNSQJ/58/POVS7+9r/////fjP+339sTMyB1mTB2z2dDass4zE3BUU4J2U650xBTUUzLjk4LjIAAAAAAAAA/+MYxAAJU2okEACNTAG/9++v/X/P9f////+uyp8Il6TGMRimB1ZjTsLkOWmwSBskEEJTEFNRTMuOTguMgAAAAAAAAAAAAAAA/+MYxAAJ+2YcCACNTA//1P9Nb9LmrJ3XWv6dP///TyMay3cjqYhoIWpExQM8ynALZwUHc9aYgpqKZlxycFxkAAAAAAAAAAAA/+MYxAAKW2YgCABHSBz/Pp6aeWoP8y//////Pz+2WGZV0OMKgUUBUxjAxGpkMBCiFDAZIFMKyYgpqKZlxycFxkAAAAAAAAAA/+MYxAAKu2YgCABNSh7/Pf/Pwy5f/l/////71Xdv3mtn/L22PUBMYmQIoYcEFlhR5g6wGbJJIjSYgpqKZlxycFxkAAAAAAAA/+MYxAAKC2IcCABNSB/Oal2Z+f/8vX/9f//f/9qqn8bN9FlNL/sfNJuuUUwd5d9CQtENjoumIKaimZccnBcZAAAAAAAAAAAA/+MYxAAJu2YkCACNTh//3//vp6fX////+TTyorVFxZGKoCrAwxwgGAH44lFvsUgRTA65epiCmopmXHJwXGQAAAAAAAAAAAAA/+MYxAAKO2IgCABNSx+W/LVazr/pn/P//////fWfO+bnzo72tqS5rlyoYd7PkgxG6KjjwJMcmIKaimZccnBcZAAAAAAAAAAA/+MYxAAIc2okCACNahrtp///////2/vZVyHGKa4I5kQ4UOSaBMxSl1ERtEjgdPExBTUUzLjk4LjIAAAAAAAAAAAAAAAAAAAA/+MYxAAJY2IkCACNTx+3/6f//r/r////9K/SqDujkdnKGDmVRJxRs6nSQ9ZTOSoDMJjkxBTUUzLjk4LjIAAAAAAAAAAAAAAA/+MYxAAKs2IUAABNMS//xevtSToh2GeV/DmxZiw/r//f+T5uYBDKKh4TMgptcCWR0cgReJASNEkxBTUUzLjk4LjIAAAAAAAA/+MYxAAK62HwCACTTR//5lLmXf3X/WWbzfb//R/TurfoYGJMYoYUVkdWK0pKxWeKRkwKk4TJSzUyYgpqKZlxycFxkAAAAAAA/+MYxAAAAANIAAAAAExBTUUzLjk4LjIAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+pub use preset_model::*;
 
 #[derive(Debug, PartialEq)]
 pub enum Operation {
     Inc,
     Dec,
 }
-pub const SOUND_LIB_PATH: &str = "/public/sounds/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    Replace,
+    Toggle,
+    Extend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaybackState {
+    #[default]
+    Idle,
+    Playing,
+    InGap,
+    Paused,
+}
+
 pub const SOUND_LIB_JSON_PATH: &str = "/public/sounds/lib.json";
 pub const GRID_ROWS_MIN: u16 = 1;
 pub const GRID_ROWS_MAX: u16 = 20;
+pub const TRACKS_MAX: usize = 4;
+pub const REPEAT_MAX: u8 = 8;
+pub const UNDO_MAX: usize = 50;
+// NOTE: Steps of each stack kept across restarts, the rest lives only in memory
+pub const UNDO_PERSIST: usize = 10;
+// NOTE: Changes closer than this form one undo step, so a slider drag is undone at once
+pub const UNDO_COALESCE_MS: f64 = 500.0;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq)]
-pub enum Category {
-    Boom,
-    Doors,
-    People,
-    Construction,
-    Eerie,
+pub fn format_filename(filename: &str) -> String {
+    format!("{}{}", filename[..1].to_uppercase(), &filename[1..],).replace("_", " ")
 }
 
-impl fmt::Display for Category {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Category::Boom => write!(f, "boom"),
-            Category::Doors => write!(f, "doors"),
-            Category::People => write!(f, "people"),
-            Category::Construction => write!(f, "construction"),
-            Category::Eerie => write!(f, "eerie"),
+// NOTE: The part of the session undo and redo step through
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Snapshot {
+    pub tracks: Vec<Track>,
+    pub volume: f32,
+    #[serde(
+        default = "grid_row_size",
+        deserialize_with = "deserialize_grid_columns"
+    )]
+    pub columns: u16,
+}
+
+// NOTE: Which control an edit came from, told apart by what it changed. Only repeated
+// changes of one control, like a slider drag, may share an undo step
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditKey {
+    Volume,
+    Track {
+        idx: usize,
+        fields: Vec<&'static str>,
+        cells: Vec<usize>,
+    },
+    Other,
+}
+
+impl EditKey {
+    pub fn between(previous: &Snapshot, current: &Snapshot) -> Self {
+        let same_tracks = previous.tracks.len() == current.tracks.len()
+            && previous
+                .tracks
+                .iter()
+                .zip(&current.tracks)
+                .all(|(a, b)| a.id == b.id);
+        if !same_tracks || previous.columns != current.columns {
+            return EditKey::Other;
+        }
+
+        let changed = (0..current.tracks.len())
+            .filter(|&idx| previous.tracks[idx] != current.tracks[idx])
+            .collect::<Vec<_>>();
+        match (previous.volume != current.volume, changed.as_slice()) {
+            (true, []) => EditKey::Volume,
+            (false, &[idx]) => {
+                let (a, b) = (&previous.tracks[idx], &current.tracks[idx]);
+                let cells = if a.grid_data.len() == b.grid_data.len() {
+                    (0..b.grid_data.len())
+                        .filter(|&cell| a.grid_data[cell] != b.grid_data[cell])
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                EditKey::Track {
+                    idx,
+                    fields: a.changed_fields(b),
+                    cells,
+                }
+            }
+            _ => EditKey::Other,
+        }
+    }
+
+    // NOTE: `last` is the key and time in ms of the edit before
+    pub fn continues(&self, last: &Option<(EditKey, f64)>, now: f64) -> bool {
+        match last {
+            Some((key, at)) => {
+                *self != EditKey::Other && key == self && now - at < UNDO_COALESCE_MS
+            }
+            None => false,
         }
     }
 }
 
-impl Category {
-    pub fn get_emoji(&self) -> char {
-        match self {
-            Category::Boom => '🏀',
-            Category::Doors => '🚪',
-            Category::People => '🤦',
-            Category::Construction => '🔨',
-            Category::Eerie => '👻',
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    // NOTE: A new edit drops the redo branch and the oldest entry past the limit
+    pub fn record(&mut self, previous: Snapshot) {
+        self.undo.push(previous);
+        if self.undo.len() > UNDO_MAX {
+            self.undo.remove(0);
         }
+        self.redo.clear();
     }
 
-    pub fn iter() -> impl Iterator<Item = Category> {
-        [
-            Category::Boom,
-            Category::Doors,
-            Category::People,
-            Category::Construction,
-            Category::Eerie,
-        ]
-        .iter()
-        .copied()
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(current);
+        Some(snapshot)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(current);
+        Some(snapshot)
     }
-}
 
-impl FromStr for Category {
-    type Err = ();
-    fn from_str(v: &str) -> Result<Category, Self::Err> {
-        match v {
-            "boom" => Ok(Category::Boom),
-            "doors" => Ok(Category::Doors),
-            "people" => Ok(Category::People),
-            "construction" => Ok(Category::Construction),
-            "eerie" => Ok(Category::Eerie),
-            _ => Err(()),
+    // NOTE: The latest steps of both stacks, what gets saved to the store
+    pub fn persisted(&self) -> History {
+        let tail = |stack: &[Snapshot]| stack[stack.len().saturating_sub(UNDO_PERSIST)..].to_vec();
+        History {
+            undo: tail(&self.undo),
+            redo: tail(&self.redo),
         }
     }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Sample {
-    pub id: String,
-    pub filename: String,
-    pub filepath: String,
-    pub category: Category,
-    pub duration: f32,
+// NOTE: Selected cells of the active track, kept sorted. A plain click only moves the
+// anchor that shift extends a range from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    cells: Vec<usize>,
+    anchor: Option<usize>,
 }
 
-impl Ord for Sample {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.filename.cmp(&other.filename)
+impl Selection {
+    pub fn select(&mut self, idx: usize, mode: SelectMode) {
+        match mode {
+            SelectMode::Replace => {
+                self.cells.clear();
+                self.anchor = Some(idx);
+            }
+            SelectMode::Toggle => {
+                match self.cells.binary_search(&idx) {
+                    Ok(pos) => {
+                        self.cells.remove(pos);
+                    }
+                    Err(pos) => self.cells.insert(pos, idx),
+                }
+                self.anchor = Some(idx);
+            }
+            SelectMode::Extend => {
+                let anchor = *self.anchor.get_or_insert(idx);
+                self.cells = (anchor.min(idx)..=anchor.max(idx)).collect();
+            }
+        }
+    }
+
+    pub fn set(&mut self, mut cells: Vec<usize>) {
+        cells.sort_unstable();
+        cells.dedup();
+        self.anchor = cells.first().copied();
+        self.cells = cells;
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.anchor = None;
+    }
+
+    pub fn cells(&self) -> &[usize] {
+        &self.cells
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        self.cells.binary_search(&idx).is_ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
     }
 }
 
-impl PartialOrd for Sample {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+// NOTE: Copied cells with their offset from the first one, pasting keeps the spacing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Clipboard(Vec<(usize, Option<Cell>)>);
+
+impl Clipboard {
+    pub fn copy(grid: &[Option<Cell>], cells: &[usize]) -> Self {
+        let first = cells.iter().min().copied().unwrap_or_default();
+        Self(
+            cells
+                .iter()
+                .filter_map(|&idx| Some((idx - first, grid.get(idx)?.clone())))
+                .collect(),
+        )
+    }
+
+    // NOTE: Cells that would land past the end of the grid are dropped. Returns where the
+    // pasted cells went
+    pub fn paste(&self, grid: &mut [Option<Cell>], at: usize) -> Vec<usize> {
+        self.0
+            .iter()
+            .filter_map(|(offset, cell)| {
+                let idx = at + offset;
+                *grid.get_mut(idx)? = cell.clone();
+                Some(idx)
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
-impl PartialEq for Sample {
-    fn eq(&self, other: &Self) -> bool {
-        self.filename == other.filename
+// NOTE: Shifts `cells` by `delta` as one block, the cells in the way take the places left
+// free. A single cell is swapped with the target. Returns false when the block would leave
+// the grid
+pub fn move_cells(grid: &mut [Option<Cell>], cells: &[usize], delta: isize) -> bool {
+    let Some(targets) = cells
+        .iter()
+        .map(|&idx| idx.checked_add_signed(delta).filter(|&to| to < grid.len()))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return false;
+    };
+
+    let moved = cells
+        .iter()
+        .map(|&idx| grid[idx].take())
+        .collect::<Vec<_>>();
+    let displaced = targets
+        .iter()
+        .filter(|to| !cells.contains(to))
+        .map(|&to| grid[to].take())
+        .collect::<Vec<_>>();
+    let freed = cells.iter().filter(|idx| !targets.contains(idx));
+
+    for (&idx, cell) in freed.zip(displaced) {
+        grid[idx] = cell;
+    }
+    for (&to, cell) in targets.iter().zip(moved) {
+        grid[to] = cell;
     }
+    true
 }
 
-impl Eq for Sample {}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOp {
+    InsertAbove,
+    InsertBelow,
+    Duplicate,
+    MoveUp,
+    MoveDown,
+    Delete,
+}
 
-pub fn format_filename(filename: &str) -> String {
-    format!("{}{}", filename[..1].to_uppercase(), &filename[1..],).replace("_", " ")
+impl RowOp {
+    pub fn iter() -> impl Iterator<Item = RowOp> {
+        [
+            RowOp::InsertAbove,
+            RowOp::InsertBelow,
+            RowOp::Duplicate,
+            RowOp::MoveUp,
+            RowOp::MoveDown,
+            RowOp::Delete,
+        ]
+        .into_iter()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RowOp::InsertAbove => "Insert row above",
+            RowOp::InsertBelow => "Insert row below",
+            RowOp::Duplicate => "Duplicate row",
+            RowOp::MoveUp => "Move row up",
+            RowOp::MoveDown => "Move row down",
+            RowOp::Delete => "Delete row",
+        }
+    }
+
+    pub fn is_allowed(&self, row: usize, rows: usize) -> bool {
+        match self {
+            RowOp::InsertAbove | RowOp::InsertBelow | RowOp::Duplicate => {
+                rows < GRID_ROWS_MAX as usize
+            }
+            RowOp::Delete => rows > GRID_ROWS_MIN as usize,
+            RowOp::MoveUp => row > 0,
+            RowOp::MoveDown => row + 1 < rows,
+        }
+    }
+
+    // NOTE: Returns false and leaves the grid as is when the op is out of bounds
+    pub fn apply(&self, grid: &mut Vec<Option<Cell>>, row: usize, row_size: usize) -> bool {
+        let rows = grid.len() / row_size;
+        if row >= rows || !self.is_allowed(row, rows) {
+            return false;
+        }
+
+        let start = row * row_size;
+        match self {
+            RowOp::InsertAbove => {
+                grid.splice(start..start, vec![None; row_size]);
+            }
+            RowOp::InsertBelow => {
+                grid.splice(start + row_size..start + row_size, vec![None; row_size]);
+            }
+            RowOp::Duplicate => {
+                let copy = grid[start..start + row_size].to_vec();
+                grid.splice(start + row_size..start + row_size, copy);
+            }
+            RowOp::MoveUp => grid[start - row_size..start + row_size].rotate_left(row_size),
+            RowOp::MoveDown => grid[start..start + row_size * 2].rotate_left(row_size),
+            RowOp::Delete => {
+                grid.drain(start..start + row_size);
+            }
+        }
+        true
+    }
+
+    // NOTE: Where a cell index points after the op, None for cells of a deleted row
+    pub fn remap(&self, idx: usize, row: usize, row_size: usize) -> Option<usize> {
+        let idx_row = idx / row_size;
+        match self {
+            RowOp::InsertAbove if idx_row >= row => Some(idx + row_size),
+            RowOp::InsertBelow | RowOp::Duplicate if idx_row > row => Some(idx + row_size),
+            RowOp::MoveUp if idx_row == row => Some(idx - row_size),
+            RowOp::MoveUp if idx_row + 1 == row => Some(idx + row_size),
+            RowOp::MoveDown if idx_row == row => Some(idx + row_size),
+            RowOp::MoveDown if idx_row == row + 1 => Some(idx - row_size),
+            RowOp::Delete if idx_row == row => None,
+            RowOp::Delete if idx_row > row => Some(idx - row_size),
+            _ => Some(idx),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Preset {
-    pub id: String,
-    pub name: String,
-    pub volume: f32,
-    pub gap_duration: u64,
-    pub random_playback: bool,
-    pub grid_data: Vec<Option<Sample>>,
-    pub created: DateTime<Utc>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternSpec {
+    pub categories: Vec<Category>,
+    // NOTE: Share of the cells left empty
+    pub density: f32,
+    pub cycle_seconds: f32,
+    pub max_duration: f32,
+    pub merge: bool,
 }
 
-impl Ord for Preset {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.created.cmp(&other.created)
+impl Default for PatternSpec {
+    fn default() -> Self {
+        Self {
+            categories: Category::iter().collect(),
+            density: 0.3,
+            cycle_seconds: 30.0,
+            max_duration: 10.0,
+            merge: false,
+        }
     }
 }
 
-impl PartialOrd for Preset {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl PatternSpec {
+    // NOTE: Fills the free cells of the track grid so one pass over it takes about
+    // `cycle_seconds`. The time left after the cells as they are is spread over the new
+    // ones, short samples repeat to reach their share. Step timing fixes the cycle, there
+    // each sample fits its step. Merging keeps the filled cells and counts them in
+    pub fn generate(
+        &self,
+        library: &HashMap<Category, Vec<Sample>>,
+        track: &Track,
+        envelope: &Envelope,
+        rng: &mut impl Rng,
+    ) -> Vec<Option<Cell>> {
+        let mut grid = if self.merge {
+            track.grid_data.clone()
+        } else {
+            vec![None; track.grid_data.len()]
+        };
+        let candidates = self
+            .categories
+            .iter()
+            .filter_map(|category| library.get(category))
+            .flatten()
+            .filter(|sample| sample.duration > 0.0 && sample.duration <= self.max_duration)
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return grid;
+        }
+
+        let mut free = (0..grid.len())
+            .filter(|&idx| grid[idx].is_none())
+            .collect::<Vec<_>>();
+        let filled = grid.len() - free.len();
+        let wanted = ((grid.len() as f32 * (1.0 - self.density)).round() as usize)
+            .saturating_sub(filled)
+            .min(free.len());
+        free.shuffle(rng);
+        free.truncate(wanted);
+        free.sort_unstable();
+
+        let span = |cell: Option<&Cell>| track.cell_span(cell, envelope);
+        let mut budget = self.cycle_seconds - grid.iter().map(|c| span(c.as_ref())).sum::<f32>();
+        for (pos, &idx) in free.iter().enumerate() {
+            // NOTE: Sounding length that makes the cell take its share of the budget
+            let ideal = match track.timing {
+                TimingMode::Gap => {
+                    budget / (free.len() - pos) as f32 + envelope.crossfade as f32 / 1000.0
+                }
+                TimingMode::Step => track.step_interval as f32 / 1000.0,
+            }
+            .max(0.0);
+            // NOTE: Any sample that fits its share will do, otherwise the shortest one
+            let fitting = candidates
+                .iter()
+                .copied()
+                .filter(|sample| sample.duration <= ideal)
+                .collect::<Vec<_>>();
+            let sample = match fitting.len() {
+                0 => candidates
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| a.duration.total_cmp(&b.duration))
+                    .unwrap(),
+                n => fitting[rng.random_range(0..n)],
+            };
+
+            let mut cell = Cell::new(sample.clone());
+            cell.repeat = (ideal / sample.duration)
+                .round()
+                .clamp(1.0, f32::from(REPEAT_MAX)) as u8;
+            budget -= span(Some(&cell)) - span(None);
+            grid[idx] = Some(cell);
+        }
+        grid
     }
 }
 
-impl PartialEq for Preset {
-    fn eq(&self, other: &Self) -> bool {
-        self.created == other.created
+fn deserialize_grid_columns<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    Ok(clamp_grid_columns(u16::deserialize(deserializer)?))
+}
+
+// NOTE: Speaker protection kept on the device, presets and schedules never carry it.
+// The ceiling is in dBFS and the max volume caps the master volume and the output level
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Limiter {
+    pub enabled: bool,
+    pub ceiling: f32,
+    pub max_volume: f32,
+    pub locked: bool,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ceiling: -1.0,
+            max_volume: 1.0,
+            locked: false,
+        }
     }
 }
 
-impl Eq for Preset {}
+impl Limiter {
+    // NOTE: Level in dBFS the output is held at. Cell gains, EQ boosts and the mixer all sit
+    // before the limiter, so the max volume is enforced here too and holds with it off
+    pub fn threshold(&self) -> f32 {
+        let lock = 20.0 * self.max_volume.clamp(0.001, 1.0).log10();
+        if self.enabled {
+            self.ceiling.clamp(-60.0, 0.0).min(lock)
+        } else {
+            lock
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlannedSchedule {
@@ -172,6 +543,7 @@ pub fn is_mobile() -> bool {
     web_sys::window().unwrap().navigator().max_touch_points() > 0
 }
 
+// NOTE: Column count of new sessions and of grids saved before the geometry was stored
 pub fn grid_row_size() -> u16 {
     if is_mobile() {
         4
@@ -179,3 +551,313 @@ pub fn grid_row_size() -> u16 {
         6
     }
 }
+
+// NOTE: Pads a grid to whole rows, one saved with another geometry may end mid-row. A grid
+// from an older save with more rows than allowed loses the extra ones
+pub fn fit_grid(grid: &mut Vec<Option<Cell>>, columns: u16) {
+    let columns = columns as usize;
+    let rows = grid
+        .len()
+        .div_ceil(columns)
+        .clamp(GRID_ROWS_MIN as usize, GRID_ROWS_MAX as usize);
+    grid.resize(rows * columns, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn cell(filename: &str) -> Option<Cell> {
+        Some(Cell::new(Sample {
+            id: format!("boom_{filename}"),
+            filename: filename.to_string(),
+            filepath: format!("/public/sounds/boom/{filename}.mp3"),
+            category: Category::Boom,
+            duration: 2.0,
+            family: family_of(filename).to_string(),
+            loop_points: None,
+        }))
+    }
+
+    fn names(grid: &[Option<Cell>]) -> Vec<&str> {
+        grid.iter()
+            .map(|cell| {
+                cell.as_ref()
+                    .map_or("", |cell| cell.sample.filename.as_str())
+            })
+            .collect()
+    }
+
+    fn snapshot(volume: f32) -> Snapshot {
+        Snapshot {
+            tracks: vec![Track::new(vec![None; 6])],
+            volume,
+            columns: 6,
+        }
+    }
+
+    #[test]
+    fn history_drops_the_oldest_step_past_the_limit() {
+        let mut history = History::default();
+        for step in 0..UNDO_MAX + 5 {
+            history.record(snapshot(step as f32));
+        }
+        let mut undone = Vec::new();
+        while let Some(snapshot) = history.undo(snapshot(-1.0)) {
+            undone.push(snapshot.volume);
+        }
+        assert_eq!(undone.len(), UNDO_MAX);
+        assert_eq!(undone.last(), Some(&5.0));
+    }
+
+    #[test]
+    fn history_keeps_the_latest_steps_of_both_stacks() {
+        let mut history = History::default();
+        for step in 0..UNDO_PERSIST * 2 {
+            history.record(snapshot(step as f32));
+        }
+        for _ in 0..UNDO_PERSIST + 2 {
+            history.undo(snapshot(-1.0));
+        }
+        let mut persisted = history.persisted();
+        assert_eq!(persisted.undo.len(), UNDO_PERSIST - 2);
+        assert_eq!(persisted.redo.len(), UNDO_PERSIST);
+        assert_eq!(persisted.undo(snapshot(-1.0)).unwrap().volume, 7.0);
+
+        history.record(snapshot(99.0));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn edits_of_one_control_share_an_undo_step() {
+        let before = snapshot(1.0);
+        let mut louder = before.clone();
+        louder.volume = 0.5;
+        assert_eq!(EditKey::between(&before, &louder), EditKey::Volume);
+
+        let mut gap = before.clone();
+        gap.tracks[0].gap_duration = 2000;
+        let mut more_gap = gap.clone();
+        more_gap.tracks[0].gap_duration = 3000;
+        let key = EditKey::between(&gap, &more_gap);
+        assert_eq!(
+            key,
+            EditKey::Track {
+                idx: 0,
+                fields: vec!["gap_duration"],
+                cells: Vec::new(),
+            }
+        );
+
+        let last = Some((key.clone(), 1000.0));
+        assert!(key.continues(&last, 1000.0 + UNDO_COALESCE_MS - 1.0));
+        assert!(!key.continues(&last, 1000.0 + UNDO_COALESCE_MS));
+        assert!(!EditKey::Volume.continues(&last, 1000.0));
+        assert!(!key.continues(&None, 1000.0));
+
+        let mut resized = before.clone();
+        resized.columns = 4;
+        let other = EditKey::between(&before, &resized);
+        assert_eq!(other, EditKey::Other);
+        assert!(!other.continues(&Some((EditKey::Other, 1000.0)), 1000.0));
+    }
+
+    #[test]
+    fn row_ops_respect_the_row_limits() {
+        let max = GRID_ROWS_MAX as usize;
+        let min = GRID_ROWS_MIN as usize;
+        for op in [RowOp::InsertAbove, RowOp::InsertBelow, RowOp::Duplicate] {
+            assert!(op.is_allowed(0, max - 1));
+            assert!(!op.is_allowed(0, max));
+            let mut grid = vec![None; max * 2];
+            assert!(!op.apply(&mut grid, 0, 2));
+            assert_eq!(grid.len(), max * 2);
+        }
+        assert!(RowOp::Delete.is_allowed(0, min + 1));
+        assert!(!RowOp::Delete.is_allowed(0, min));
+        let mut grid = vec![cell("a"); min * 2];
+        assert!(!RowOp::Delete.apply(&mut grid, 0, 2));
+        assert!(!RowOp::MoveUp.is_allowed(0, 3));
+        assert!(!RowOp::MoveDown.is_allowed(2, 3));
+        assert!(!RowOp::MoveDown.apply(&mut vec![None; 6], 3, 2));
+    }
+
+    #[test]
+    fn row_ops_and_remap_agree() {
+        let grid = vec![
+            cell("a"),
+            cell("b"),
+            cell("c"),
+            cell("d"),
+            cell("e"),
+            cell("f"),
+        ];
+        for op in RowOp::iter() {
+            for row in 0..3 {
+                let mut applied = grid.clone();
+                if !op.apply(&mut applied, row, 2) {
+                    continue;
+                }
+                for (idx, before) in grid.iter().enumerate() {
+                    match op.remap(idx, row, 2) {
+                        Some(to) => assert_eq!(&applied[to], before, "{op:?} row {row}"),
+                        None => assert!(op == RowOp::Delete && idx / 2 == row),
+                    }
+                }
+            }
+        }
+
+        let mut moved = grid.clone();
+        assert!(RowOp::MoveDown.apply(&mut moved, 0, 2));
+        assert_eq!(names(&moved), ["c", "d", "a", "b", "e", "f"]);
+        let mut duplicated = grid.clone();
+        assert!(RowOp::Duplicate.apply(&mut duplicated, 2, 2));
+        assert_eq!(names(&duplicated), ["a", "b", "c", "d", "e", "f", "e", "f"]);
+    }
+
+    #[test]
+    fn fit_grid_pads_and_truncates_to_whole_rows() {
+        let mut grid = vec![cell("a"); 7];
+        fit_grid(&mut grid, 3);
+        assert_eq!(grid.len(), 9);
+        let mut grid = Vec::new();
+        fit_grid(&mut grid, 4);
+        assert_eq!(grid.len(), GRID_ROWS_MIN as usize * 4);
+        let mut grid = vec![None; (GRID_ROWS_MAX as usize + 3) * 2];
+        fit_grid(&mut grid, 2);
+        assert_eq!(grid.len(), GRID_ROWS_MAX as usize * 2);
+    }
+
+    #[test]
+    fn move_cells_stops_at_the_grid_edge() {
+        let grid = vec![cell("a"), cell("b"), None, cell("d"), cell("e")];
+        let mut moved = grid.clone();
+        assert!(!move_cells(&mut moved, &[3, 4], 1));
+        assert!(!move_cells(&mut moved, &[0, 1], -1));
+        assert_eq!(moved, grid);
+
+        assert!(move_cells(&mut moved, &[0, 1], 3));
+        assert_eq!(names(&moved), ["d", "e", "", "a", "b"]);
+        assert!(move_cells(&mut moved, &[4], -4));
+        assert_eq!(names(&moved), ["b", "e", "", "a", "d"]);
+    }
+
+    #[test]
+    fn paste_drops_cells_past_the_grid_end() {
+        let grid = vec![cell("a"), None, cell("c"), cell("d")];
+        let clipboard = Clipboard::copy(&grid, &[0, 2]);
+        let mut pasted = vec![None; 4];
+        assert_eq!(clipboard.paste(&mut pasted, 1), vec![1, 3]);
+        assert_eq!(names(&pasted), ["", "a", "", "c"]);
+        let mut pasted = vec![None; 4];
+        assert_eq!(clipboard.paste(&mut pasted, 3), vec![3]);
+        assert_eq!(names(&pasted), ["", "", "", "a"]);
+        assert!(Clipboard::copy(&grid, &[]).is_empty());
+    }
+
+    fn library() -> HashMap<Category, Vec<Sample>> {
+        let sample = |category: Category, filename: &str, duration: f32| Sample {
+            id: format!("{category}_{filename}"),
+            filename: filename.to_string(),
+            filepath: format!("/public/sounds/{category}/{filename}.mp3"),
+            category,
+            duration,
+            family: family_of(filename).to_string(),
+            loop_points: None,
+        };
+        HashMap::from([
+            (
+                Category::Boom,
+                vec![
+                    sample(Category::Boom, "boom_1", 1.0),
+                    sample(Category::Boom, "boom_2", 2.0),
+                    sample(Category::Boom, "boom_3", 30.0),
+                ],
+            ),
+            (
+                Category::Doors,
+                vec![sample(Category::Doors, "door_1", 4.0)],
+            ),
+            (
+                Category::Eerie,
+                vec![sample(Category::Eerie, "wind_1", 0.5)],
+            ),
+        ])
+    }
+
+    #[test]
+    fn pattern_fills_the_cycle() {
+        let spec = PatternSpec {
+            categories: vec![Category::Boom, Category::Doors],
+            density: 0.5,
+            cycle_seconds: 60.0,
+            max_duration: 10.0,
+            merge: false,
+        };
+        let track = Track::new(vec![cell("a"); 12]);
+        let envelope = Envelope::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        let grid = spec.generate(&library(), &track, &envelope, &mut rng);
+
+        assert_eq!(grid.len(), 12);
+        let cells = grid.iter().flatten().collect::<Vec<_>>();
+        assert_eq!(cells.len(), 6);
+        for cell in &cells {
+            assert!(spec.categories.contains(&cell.sample.category));
+            assert!(cell.sample.duration <= spec.max_duration);
+            assert!((1..=REPEAT_MAX).contains(&cell.repeat));
+        }
+        let filled = Track {
+            grid_data: grid,
+            ..track
+        };
+        assert!((filled.cycle_length(&envelope) - spec.cycle_seconds).abs() < 0.5);
+    }
+
+    #[test]
+    fn pattern_merge_keeps_the_filled_cells() {
+        let spec = PatternSpec {
+            density: 0.0,
+            merge: true,
+            ..PatternSpec::default()
+        };
+        let mut grid = vec![None; 6];
+        grid[1] = cell("a");
+        grid[4] = cell("b");
+        let track = Track::new(grid);
+        let mut rng = StdRng::seed_from_u64(7);
+        let merged = spec.generate(&library(), &track, &Envelope::default(), &mut rng);
+        assert_eq!(merged[1], track.grid_data[1]);
+        assert_eq!(merged[4], track.grid_data[4]);
+        assert!(merged.iter().all(Option::is_some));
+
+        let none = PatternSpec {
+            categories: vec![Category::People],
+            ..spec
+        };
+        assert_eq!(
+            none.generate(&library(), &track, &Envelope::default(), &mut rng),
+            track.grid_data
+        );
+    }
+
+    #[test]
+    fn pattern_fits_samples_to_the_step() {
+        let spec = PatternSpec {
+            density: 0.0,
+            ..PatternSpec::default()
+        };
+        let track = Track {
+            timing: TimingMode::Step,
+            step_interval: 2500,
+            ..Track::new(vec![None; 8])
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let grid = spec.generate(&library(), &track, &Envelope::default(), &mut rng);
+        for cell in grid.iter().flatten() {
+            assert!(cell.sample.duration <= 2.5);
+        }
+    }
+}